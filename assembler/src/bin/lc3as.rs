@@ -0,0 +1,305 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io, process};
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+
+use pest::Parser as _;
+
+use assembler::output::{Color, ColorChoice, Painter};
+use assembler::{
+    assemble_with_resolver, diagnostics_to_json, disassembler, format_ast, format_pair, parse,
+    Assembly, Lc3Parser, Rule,
+};
+
+/// LC-3 assembler.
+#[derive(Parser)]
+#[command(name = "lc3as")]
+struct Args {
+    /// Source files, `-` meaning stdin. Without `--check` this is the input
+    /// plus an optional output file, kept for compatibility with the old
+    /// `lc3as <input> <output>` invocation.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Validate the inputs without writing any output.
+    #[arg(long)]
+    check: bool,
+
+    /// Output file, or `-` to write to stdout. Defaults to the input name
+    /// with an `.obj` extension (stdout when the input is stdin).
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Output format.
+    #[arg(long, default_value = "obj", value_parser = ["obj", "hex", "bin", "carray", "ihex"])]
+    format: String,
+
+    /// Print diagnostics as JSON on stdout instead of plain text.
+    #[arg(long)]
+    json_diagnostics: bool,
+
+    /// Do not write the `.sym` symbol file next to the object.
+    #[arg(long)]
+    no_sym: bool,
+
+    /// Disassemble an object file instead of assembling.
+    #[arg(long)]
+    disassemble: bool,
+
+    /// Print the pest parse tree to stdout and exit, without assembling.
+    #[arg(long)]
+    print_ast: bool,
+
+    /// Print the parsed [`AstNode`] tree to stdout and exit, without
+    /// assembling.
+    #[arg(long)]
+    print_ir: bool,
+
+    /// Drop the origin header from `obj` output.
+    #[arg(long)]
+    raw: bool,
+
+    /// Byte order of `obj` output.
+    #[arg(long, default_value = "big", value_parser = ["little", "big"])]
+    endian: String,
+
+    /// When to color diagnostics.
+    #[arg(long, default_value = "auto", value_parser = ["auto", "always", "never"])]
+    color: String,
+
+    /// Also write a listing file to the given path.
+    #[arg(long)]
+    listing: Option<PathBuf>,
+}
+
+/// Prints an error and exits with code 1. Usage errors exit with code 2
+/// through clap before we get here.
+fn fail(message: impl fmt::Display) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+fn write_output(path: &Path, bytes: &[u8]) {
+    let result = if is_stdio(path) {
+        io::stdout().lock().write_all(bytes)
+    } else {
+        fs::write(path, bytes)
+    };
+    result.unwrap_or_else(|error| {
+        fail(format!(
+            "could not write \"{}\": {}",
+            path.display(),
+            error
+        ))
+    });
+}
+
+/// Reads a source file, `-` meaning stdin.
+fn read_source(input: &Path) -> String {
+    if is_stdio(input) {
+        let mut buffer = String::new();
+        io::stdin()
+            .read_to_string(&mut buffer)
+            .unwrap_or_else(|error| fail(format!("could not read stdin: {}", error)));
+        buffer
+    } else {
+        fs::read_to_string(input).unwrap_or_else(|error| {
+            fail(format!(
+                "could not read \"{}\": {}",
+                input.display(),
+                error
+            ))
+        })
+    }
+}
+
+/// Runs the full assembly pipeline (parse plus both emitter passes) for
+/// one input, resolving includes relative to its directory. Writing any
+/// output is up to the caller, which is what makes `--check` possible.
+fn assemble_source(input: &Path, source: &str) -> Result<Assembly, assembler::ErrorWithPosition> {
+    let base_dir = if is_stdio(input) {
+        PathBuf::from(".")
+    } else {
+        input.parent().unwrap_or(Path::new(".")).to_path_buf()
+    };
+    let resolver = move |path: &str| {
+        fs::read_to_string(base_dir.join(path)).map_err(|error| error.to_string())
+    };
+    assemble_with_resolver(source, resolver)
+}
+
+fn main() {
+    let args = Args::parse();
+    let little_endian = args.endian == "little";
+    let color = ColorChoice::from_flag(&args.color).unwrap_or_else(|error| fail(error));
+    let painter = Painter::new(color);
+
+    if args.check {
+        let mut failed = 0;
+        for input in &args.files {
+            let source = read_source(input);
+            match assemble_source(input, &source) {
+                Ok(_) => println!("{}: OK", input.display()),
+                Err(error) => {
+                    eprintln!(
+                        "{}: {}{}",
+                        input.display(),
+                        painter.paint(Color::Red, "error: "),
+                        error
+                    );
+                    failed += 1;
+                }
+            }
+        }
+        if args.files.len() > 1 {
+            println!("{} of {} files OK", args.files.len() - failed, args.files.len());
+        }
+        process::exit(if failed > 0 { 1 } else { 0 });
+    }
+
+    if args.files.len() > 2 {
+        Args::command()
+            .error(
+                ErrorKind::TooManyValues,
+                "expected at most an input and an output file (use --check to validate several)",
+            )
+            .exit();
+    }
+    let input = &args.files[0];
+    let from_stdin = is_stdio(input);
+
+    if args.disassemble {
+        let bytes = if from_stdin {
+            let mut buffer = Vec::new();
+            io::stdin()
+                .read_to_end(&mut buffer)
+                .unwrap_or_else(|error| fail(format!("could not read stdin: {}", error)));
+            buffer
+        } else {
+            fs::read(input).unwrap_or_else(|error| {
+                fail(format!(
+                    "could not read \"{}\": {}",
+                    input.display(),
+                    error
+                ))
+            })
+        };
+        if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+            fail(format!("Invalid object file length {}", bytes.len()));
+        }
+        let words: Vec<u16> = bytes
+            .chunks(2)
+            .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+            .collect();
+        for line in disassembler::disassemble(&words[1..], words[0]) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    let source = read_source(input);
+
+    // Debugging aids for grammar work: dump what the parser saw instead of
+    // producing an object file.
+    if args.print_ast || args.print_ir {
+        if args.print_ast {
+            let pairs = Lc3Parser::parse(Rule::program, &source)
+                .unwrap_or_else(|error| fail(error));
+            for pair in pairs {
+                println!("{}", format_pair(pair, 0, false));
+            }
+        }
+        if args.print_ir {
+            let ast = parse(&source).unwrap_or_else(|error| fail(error));
+            print!("{}", format_ast(&ast));
+        }
+        return;
+    }
+
+    // `-o` wins over the compatibility positional; without either the object
+    // lands next to the input, or on stdout when the source came from stdin.
+    let output = args
+        .output
+        .clone()
+        .or_else(|| args.files.get(1).cloned())
+        .unwrap_or_else(|| {
+            if from_stdin {
+                PathBuf::from("-")
+            } else {
+                input.with_extension("obj")
+            }
+        });
+    let to_stdout = is_stdio(&output);
+
+    let assembly = match assemble_source(input, &source) {
+        Ok(assembly) => assembly,
+        Err(error) => {
+            if args.json_diagnostics {
+                println!("{}", diagnostics_to_json(&[error.to_diagnostic()]));
+            } else {
+                eprintln!("{}{}", painter.paint(Color::Red, "error: "), error);
+            }
+            process::exit(1);
+        }
+    };
+    if args.json_diagnostics {
+        println!("{}", diagnostics_to_json(assembly.warnings()));
+    }
+
+    match args.format.as_str() {
+        "obj" => {
+            // `--raw` drops the origin header for tools that load the
+            // words at a known address anyway.
+            let words = if args.raw {
+                assembly.words()
+            } else {
+                assembly.data()
+            };
+            let mut bytes = Vec::with_capacity(words.len() * 2);
+            for word in words {
+                if little_endian {
+                    bytes.extend_from_slice(&word.to_le_bytes());
+                } else {
+                    bytes.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+            write_output(&output, &bytes);
+        }
+        "hex" => write_output(&output, assembly.to_hex_dump().as_bytes()),
+        "bin" => write_output(&output, assembly.to_bin_text().as_bytes()),
+        "carray" => write_output(&output, assembly.to_c_array().as_bytes()),
+        "ihex" => write_output(&output, assembly.to_intel_hex().as_bytes()),
+        _ => unreachable!("clap validates --format"),
+    }
+
+    if let Some(path) = args.listing {
+        write_output(&path, assembly.to_listing(&source).as_bytes());
+    }
+
+    // A `.sym` path cannot be derived when the object goes to stdout.
+    if !args.no_sym && !to_stdout {
+        let sym_path = output.with_extension("sym");
+        let mut file = fs::File::create(&sym_path).unwrap_or_else(|error| {
+            fail(format!(
+                "could not create \"{}\": {}",
+                sym_path.display(),
+                error
+            ))
+        });
+        assembly
+            .write_symbol_file(&mut file)
+            .unwrap_or_else(|error| {
+                fail(format!(
+                    "could not write \"{}\": {}",
+                    sym_path.display(),
+                    error
+                ))
+            });
+    }
+}
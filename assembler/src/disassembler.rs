@@ -0,0 +1,150 @@
+//! Turns memory words back into assembly text.
+//!
+//! Decoding goes through the shared `lc3-isa` instruction table and is
+//! strict: words that violate the ISA's fixed fields, the reserved 0b1101
+//! opcode and plain data all come out as `.FILL xNNNN`, so the output
+//! assembles back to exactly the input words.
+
+use std::collections::HashSet;
+
+use lc3_isa::instruction::{Instruction, Registers};
+
+/// Disassembles `words` loaded at `origin` into a re-assemblable program,
+/// including the `.ORIG`/`.END` bracket. Branch and subroutine targets
+/// within the disassembled range get synthesized `L_XXXX` labels.
+pub fn disassemble(words: &[u16], origin: u16) -> Vec<String> {
+    let end = origin.wrapping_add(words.len() as u16);
+    let in_range = |address: u16| address >= origin && address < end;
+
+    // First pass: find every PC-relative branch/JSR target we can label.
+    let mut targets = HashSet::new();
+    for (index, word) in words.iter().enumerate() {
+        let address = origin.wrapping_add(index as u16);
+        if let Some(target) = branch_target(*word, address) {
+            if in_range(target) {
+                targets.insert(target);
+            }
+        }
+    }
+
+    let mut lines = vec![format!(".ORIG x{:04X}", origin)];
+    for (index, word) in words.iter().enumerate() {
+        let address = origin.wrapping_add(index as u16);
+        let text = render(*word, address, &targets, in_range);
+        if targets.contains(&address) {
+            lines.push(format!("L_{:04X} {}", address, text));
+        } else {
+            lines.push(text);
+        }
+    }
+    lines.push(".END".to_string());
+    lines
+}
+
+/// The PC-relative target of a BR or JSR word, if it is one.
+fn branch_target(word: u16, address: u16) -> Option<u16> {
+    match Instruction::from_raw(word) {
+        // A BR with no condition bits is a NOP (or data), not a branch.
+        Instruction::Br { n: false, z: false, p: false, .. } => None,
+        Instruction::Br { pc_offset9, .. } => {
+            Some(address.wrapping_add(1).wrapping_add(pc_offset9))
+        }
+        Instruction::Jsr { pc_offset11 } => {
+            Some(address.wrapping_add(1).wrapping_add(pc_offset11))
+        }
+        _ => None,
+    }
+}
+
+fn render(
+    word: u16,
+    address: u16,
+    targets: &HashSet<u16>,
+    in_range: impl Fn(u16) -> bool,
+) -> String {
+    let target = |offset: u16| offset_operand(offset, address, targets, &in_range);
+    match Instruction::from_raw_strict(word) {
+        Instruction::Br { n: false, z: false, p: false, .. } => {
+            // No condition bits: NOP when the offset is zero too, otherwise
+            // unreachable data.
+            if word == 0 {
+                "NOP".to_string()
+            } else {
+                fill(word)
+            }
+        }
+        Instruction::Br { n, z, p, pc_offset9 } => {
+            let mut mnemonic = String::from("BR");
+            if n {
+                mnemonic.push('n');
+            }
+            if z {
+                mnemonic.push('z');
+            }
+            if p {
+                mnemonic.push('p');
+            }
+            format!("{} {}", mnemonic, target(pc_offset9))
+        }
+        Instruction::AddImmediate { dr, sr1, imm5 } => {
+            format!("ADD {:?}, {:?}, #{}", dr, sr1, imm5 as i16)
+        }
+        Instruction::AddRegister { dr, sr1, sr2 } => {
+            format!("ADD {:?}, {:?}, {:?}", dr, sr1, sr2)
+        }
+        Instruction::AndImmediate { dr, sr1, imm5 } => {
+            format!("AND {:?}, {:?}, #{}", dr, sr1, imm5 as i16)
+        }
+        Instruction::AndRegister { dr, sr1, sr2 } => {
+            format!("AND {:?}, {:?}, {:?}", dr, sr1, sr2)
+        }
+        Instruction::Ld { dr, pc_offset9 } => format!("LD {:?}, {}", dr, target(pc_offset9)),
+        Instruction::Ldi { dr, pc_offset9 } => format!("LDI {:?}, {}", dr, target(pc_offset9)),
+        Instruction::Lea { dr, pc_offset9 } => format!("LEA {:?}, {}", dr, target(pc_offset9)),
+        Instruction::St { sr, pc_offset9 } => format!("ST {:?}, {}", sr, target(pc_offset9)),
+        Instruction::Sti { sr, pc_offset9 } => format!("STI {:?}, {}", sr, target(pc_offset9)),
+        Instruction::Jsr { pc_offset11 } => format!("JSR {}", target(pc_offset11)),
+        Instruction::JsrRegister { base_r } => format!("JSRR {:?}", base_r),
+        Instruction::Ldr { dr, base_r, offset6 } => {
+            format!("LDR {:?}, {:?}, #{}", dr, base_r, offset6 as i16)
+        }
+        Instruction::Str { sr, base_r, offset6 } => {
+            format!("STR {:?}, {:?}, #{}", sr, base_r, offset6 as i16)
+        }
+        Instruction::Rti {} => "RTI".to_string(),
+        Instruction::Not { dr, sr } => format!("NOT {:?}, {:?}", dr, sr),
+        Instruction::Jmp { base_r: Registers::R7 } => "RET".to_string(),
+        Instruction::Jmp { base_r } => format!("JMP {:?}", base_r),
+        Instruction::Trap { trapvect8 } => match trapvect8 {
+            0x20 => "GETC".to_string(),
+            0x21 => "OUT".to_string(),
+            0x22 => "PUTS".to_string(),
+            0x23 => "IN".to_string(),
+            0x24 => "PUTSP".to_string(),
+            0x25 => "HALT".to_string(),
+            vector => format!("TRAP x{:02X}", vector),
+        },
+        // The reserved 0b1101 opcode and fixed-field violations are data.
+        Instruction::Reserved {} | Instruction::Malformed { .. } => fill(word),
+    }
+}
+
+/// Renders a PC-relative operand: the synthesized label when the target is
+/// inside the disassembled range, a raw signed offset otherwise.
+fn offset_operand(
+    offset: u16,
+    address: u16,
+    targets: &HashSet<u16>,
+    in_range: &impl Fn(u16) -> bool,
+) -> String {
+    let target = address.wrapping_add(1).wrapping_add(offset);
+    if in_range(target) && targets.contains(&target) {
+        format!("L_{:04X}", target)
+    } else {
+        format!("#{}", offset as i16)
+    }
+}
+
+fn fill(word: u16) -> String {
+    format!(".FILL x{:04X}", word)
+}
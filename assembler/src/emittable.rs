@@ -0,0 +1,749 @@
+//! Turns parsed instructions into memory words.
+//!
+//! An [`Emittable`] is created for every instruction during the emitter's
+//! first pass (so label offsets can be computed from its [`size`]) and
+//! produces its final words during the second pass, once all labels are
+//! known.
+//!
+//! [`size`]: Emittable::size
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use lc3_isa::fields;
+use lc3_isa::instruction::{Instruction, Registers};
+use pest::Span;
+
+use crate::{AstNode, Constant, ErrorWithPosition, MemoryLocation, Opcode, PositionContext};
+
+/// A directive handler: estimates how many words the directive occupies
+/// during the first emitter pass and produces them during the second, once
+/// labels are resolved. The built-in `.FILL`/`.BLKW`/`.STRINGZ` go through
+/// this interface too, so a registered handler can even replace them.
+pub trait PseudoOp {
+    /// The number of memory words the directive will occupy. `constants`
+    /// holds the `.EQU` definitions seen so far, so a count operand must
+    /// be defined before its first use; sizing and [`emit`] must agree or
+    /// every later address would be wrong (the emitter checks).
+    ///
+    /// [`emit`]: PseudoOp::emit
+    fn size(&self, operands: &[AstNode<'_>], constants: &HashMap<String, Constant>) -> u16;
+
+    /// The words to emit. Errors are plain strings; the emitter attaches
+    /// the source position.
+    fn emit(
+        &self,
+        operands: &[AstNode<'_>],
+        context: &EmitContext<'_>,
+    ) -> Result<Vec<u16>, String>;
+}
+
+/// Everything a [`PseudoOp`] may need during the second emitter pass.
+pub struct EmitContext<'c> {
+    /// The address the directive's first word lands at.
+    pub offset: u16,
+    /// The label table from the first pass.
+    pub labels: &'c HashMap<String, MemoryLocation>,
+    /// `.EQU` constants from the first pass.
+    pub constants: &'c HashMap<String, Constant>,
+    /// Whether label references fold case before matching.
+    pub case_insensitive_labels: bool,
+}
+
+impl EmitContext<'_> {
+    /// Resolves a label reference, folding its case when the assembly was
+    /// run with case-insensitive labels.
+    pub fn label(&self, name: &str) -> Option<&MemoryLocation> {
+        lookup_label(self.labels, name, self.case_insensitive_labels)
+    }
+}
+
+/// Maps upper-cased directive names to their [`PseudoOp`] handlers.
+#[derive(Clone, Default)]
+pub struct PseudoOpRegistry {
+    handlers: HashMap<String, Rc<dyn PseudoOp>>,
+}
+
+impl PseudoOpRegistry {
+    /// A registry holding the built-in directives that fit the interface:
+    /// `.FILL`, `.BLKW` and `.STRINGZ`.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+        registry.register(".FILL", Rc::new(FillOp));
+        registry.register(".BLKW", Rc::new(BlkwOp));
+        registry.register(".STRINGZ", Rc::new(StringzOp));
+        registry
+    }
+
+    /// Registers `handler` for the directive `name` (e.g. `".WORDSWAP"`),
+    /// replacing any previous handler of that name.
+    pub fn register(&mut self, name: &str, handler: Rc<dyn PseudoOp>) {
+        self.handlers.insert(name.to_ascii_uppercase(), handler);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<dyn PseudoOp>> {
+        self.handlers.get(&name.to_ascii_uppercase())
+    }
+}
+
+impl fmt::Debug for PseudoOpRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.handlers.keys().collect();
+        names.sort();
+        f.debug_tuple("PseudoOpRegistry").field(&names).finish()
+    }
+}
+
+/// The built-in `.FILL` handler: a single word holding an immediate, a
+/// label's absolute address, or a constant.
+pub struct FillOp;
+
+impl PseudoOp for FillOp {
+    fn size(&self, _operands: &[AstNode<'_>], _constants: &HashMap<String, Constant>) -> u16 {
+        1
+    }
+
+    fn emit(
+        &self,
+        operands: &[AstNode<'_>],
+        context: &EmitContext<'_>,
+    ) -> Result<Vec<u16>, String> {
+        let value = match &operands[0] {
+            AstNode::Label { name, .. } => context
+                .label(name)
+                .map(|location| location.address)
+                .or_else(|| context.constants.get(*name).map(|constant| constant.value))
+                .ok_or_else(|| undefined_label(name, context.labels, context.constants))?,
+            AstNode::AdjustedLabel { name, offset, .. } => context
+                .label(name)
+                .map(|location| location.address)
+                .or_else(|| context.constants.get(*name).map(|constant| constant.value))
+                .map(|value| value.wrapping_add(*offset as u16))
+                .ok_or_else(|| undefined_label(name, context.labels, context.constants))?,
+            _ => immediate_operand(operands, 0, context.constants)?,
+        };
+        Ok(vec![value])
+    }
+}
+
+/// The built-in `.BLKW` handler: a block of `count` words, optionally
+/// filled with a value other than zero.
+pub struct BlkwOp;
+
+impl PseudoOp for BlkwOp {
+    fn size(&self, operands: &[AstNode<'_>], constants: &HashMap<String, Constant>) -> u16 {
+        if operands.is_empty() {
+            return 0;
+        }
+        // Resolved exactly like `emit` resolves it, so a `.EQU` count
+        // sizes the block correctly in pass 1. Missing, non-positive or
+        // undefined counts size as zero; `emit` has the precise error.
+        match immediate_operand(operands, 0, constants) {
+            Ok(count) if count as i16 > 0 => count,
+            _ => 0,
+        }
+    }
+
+    fn emit(
+        &self,
+        operands: &[AstNode<'_>],
+        context: &EmitContext<'_>,
+    ) -> Result<Vec<u16>, String> {
+        let count = immediate_operand(operands, 0, context.constants)?;
+        if count as i16 <= 0 {
+            return Err(format!(
+                "'.BLKW' expects a positive word count, got {}",
+                count as i16
+            ));
+        }
+        let fill = match operands.get(1) {
+            Some(_) => immediate_operand(operands, 1, context.constants)?,
+            None => 0,
+        };
+        Ok(vec![fill; count as usize])
+    }
+}
+
+/// The built-in `.STRINGZ` handler: the decoded characters of the string
+/// operands (adjacent literals concatenate, C-style) plus a terminating
+/// zero word.
+pub struct StringzOp;
+
+impl PseudoOp for StringzOp {
+    fn size(&self, operands: &[AstNode<'_>], _constants: &HashMap<String, Constant>) -> u16 {
+        string_char_count(operands) + 1
+    }
+
+    fn emit(
+        &self,
+        operands: &[AstNode<'_>],
+        _context: &EmitContext<'_>,
+    ) -> Result<Vec<u16>, String> {
+        if operands.is_empty() {
+            return Err("'.STRINGZ' expects at least one string operand".to_string());
+        }
+        let mut words = Vec::new();
+        for operand in operands {
+            match operand {
+                AstNode::StringLiteral(text) => {
+                    let decoded = decode_string(text)?;
+                    words.extend(decoded.chars().map(|c| c as u16));
+                }
+                other => {
+                    return Err(format!(
+                        "'.STRINGZ' expects string operands, got {:?}",
+                        other
+                    ));
+                }
+            }
+        }
+        words.push(0);
+        Ok(words)
+    }
+}
+
+/// Total decoded character count across the string operands. Operands that
+/// are not strings (or fail to decode) count as zero here; `emit` reports
+/// the error.
+fn string_char_count(operands: &[AstNode<'_>]) -> u16 {
+    operands
+        .iter()
+        .map(|operand| match operand {
+            AstNode::StringLiteral(text) => decode_string(text)
+                .map(|decoded| decoded.chars().count() as u16)
+                .unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Resolves the operand at `index` to an immediate, through `.EQU`
+/// constants if it is a name.
+fn immediate_operand(
+    operands: &[AstNode<'_>],
+    index: usize,
+    constants: &HashMap<String, Constant>,
+) -> Result<u16, String> {
+    match &operands[index] {
+        AstNode::ImmediateOperand(value) => Ok(*value),
+        AstNode::Label { name, .. } => constants
+            .get(*name)
+            .map(|constant| constant.value)
+            .ok_or_else(|| format!("Undefined constant '{}'", name)),
+        AstNode::AdjustedLabel { name, offset, .. } => constants
+            .get(*name)
+            .map(|constant| constant.value.wrapping_add(*offset as u16))
+            .ok_or_else(|| format!("Undefined constant '{}'", name)),
+        other => Err(format!("Expected an immediate operand, got {:?}", other)),
+    }
+}
+
+fn lookup_label<'m>(
+    labels: &'m HashMap<String, MemoryLocation>,
+    name: &str,
+    case_insensitive: bool,
+) -> Option<&'m MemoryLocation> {
+    if case_insensitive {
+        labels.get(&name.to_ascii_uppercase())
+    } else {
+        labels.get(name)
+    }
+}
+
+pub struct Emittable<'a> {
+    address: u16,
+    opcode: Option<Opcode>,
+    handler: Option<Rc<dyn PseudoOp>>,
+    operands: Vec<AstNode<'a>>,
+    span: Span<'a>,
+    case_insensitive_labels: bool,
+}
+
+impl<'a> Emittable<'a> {
+    pub fn from(opcode: Opcode, operands: Vec<AstNode<'a>>, address: u16, span: Span<'a>) -> Self {
+        Self {
+            address,
+            opcode: Some(opcode),
+            handler: None,
+            operands,
+            span,
+            case_insensitive_labels: false,
+        }
+    }
+
+    /// An emittable for a registry-only directive that has no `Opcode`;
+    /// attach its handler via [`with_pseudo_op`](Emittable::with_pseudo_op).
+    pub fn pseudo(operands: Vec<AstNode<'a>>, address: u16, span: Span<'a>) -> Self {
+        Self {
+            address,
+            opcode: None,
+            handler: None,
+            operands,
+            span,
+            case_insensitive_labels: false,
+        }
+    }
+
+    /// Routes sizing and emission through `handler` instead of the built-in
+    /// opcode logic.
+    pub fn with_pseudo_op(mut self, handler: Rc<dyn PseudoOp>) -> Self {
+        self.handler = Some(handler);
+        self
+    }
+
+    /// Makes label references resolve case-insensitively; the label table
+    /// must have been recorded with upper-cased keys to match.
+    pub fn with_case_insensitive_labels(mut self, case_insensitive_labels: bool) -> Self {
+        self.case_insensitive_labels = case_insensitive_labels;
+        self
+    }
+
+    fn context<'c>(
+        &self,
+        labels: &'c HashMap<String, MemoryLocation>,
+        constants: &'c HashMap<String, Constant>,
+    ) -> EmitContext<'c> {
+        EmitContext {
+            offset: self.address,
+            labels,
+            constants,
+            case_insensitive_labels: self.case_insensitive_labels,
+        }
+    }
+
+    /// Looks up a label reference, folding its case when the assembly was
+    /// run with case-insensitive labels.
+    fn label<'m>(
+        &self,
+        labels: &'m HashMap<String, MemoryLocation>,
+        name: &str,
+    ) -> Option<&'m MemoryLocation> {
+        lookup_label(labels, name, self.case_insensitive_labels)
+    }
+
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    pub fn span(&self) -> &Span<'a> {
+        &self.span
+    }
+
+    /// The number of memory words this emittable occupies.
+    pub fn size(&self, constants: &HashMap<String, Constant>) -> u16 {
+        if let Some(handler) = &self.handler {
+            return handler.size(&self.operands, constants);
+        }
+        // Registry-only directives always carry a handler, so a bare
+        // pseudo emittable contributes nothing.
+        let Some(opcode) = self.opcode else { return 0 };
+        match opcode {
+            Opcode::Blkw => BlkwOp.size(&self.operands, constants),
+            Opcode::Stringz => StringzOp.size(&self.operands, constants),
+            // Two characters per word, plus the zero terminator word.
+            Opcode::Stringzp => string_char_count(&self.operands).div_ceil(2) + 1,
+            Opcode::Assert => 4,
+            _ => 1,
+        }
+    }
+
+    pub fn emit(
+        &self,
+        labels: &HashMap<String, MemoryLocation>,
+        constants: &HashMap<String, Constant>,
+    ) -> Result<Vec<u16>, ErrorWithPosition> {
+        let position = self.span.start_pos();
+        if let Some(handler) = &self.handler {
+            return handler
+                .emit(&self.operands, &self.context(labels, constants))
+                .with_position(position);
+        }
+        let Some(opcode) = self.opcode else {
+            return Err(ErrorWithPosition::new(
+                "Pseudo-op emittable without a registered handler",
+                position,
+            ));
+        };
+        match opcode {
+            Opcode::Add | Opcode::And => {
+                let dr = self.register(0).with_position(position)?;
+                let sr1 = self.register(1).with_position(position)?;
+                let instruction = match self.operands.get(2) {
+                    Some(AstNode::RegisterOperand(sr2)) => {
+                        let sr2 = Registers::from_u16_or_panic(*sr2 as u16);
+                        if opcode == Opcode::Add {
+                            Instruction::AddRegister { dr, sr1, sr2 }
+                        } else {
+                            Instruction::AndRegister { dr, sr1, sr2 }
+                        }
+                    }
+                    _ => {
+                        let imm = self.immediate(2, constants).with_position(position)?;
+                        let imm5 = self.signed_field(imm, fields::IMM5).with_position(position)?;
+                        if opcode == Opcode::Add {
+                            Instruction::AddImmediate { dr, sr1, imm5 }
+                        } else {
+                            Instruction::AndImmediate { dr, sr1, imm5 }
+                        }
+                    }
+                };
+                Ok(vec![instruction.to_raw()])
+            }
+            Opcode::Br { n, z, p } => {
+                let offset = self
+                    .pc_offset(0, labels, constants, fields::PC_OFFSET9)
+                    .with_position(position)?;
+                Ok(vec![Instruction::Br { n, z, p, pc_offset9: offset }.to_raw()])
+            }
+            Opcode::Jmp => {
+                let base_r = self.register(0).with_position(position)?;
+                Ok(vec![Instruction::Jmp { base_r }.to_raw()])
+            }
+            Opcode::Jsrr => {
+                let base_r = self.register(0).with_position(position)?;
+                Ok(vec![Instruction::JsrRegister { base_r }.to_raw()])
+            }
+            Opcode::Jsr => {
+                let offset = self
+                    .pc_offset(0, labels, constants, fields::PC_OFFSET11)
+                    .with_position(position)?;
+                Ok(vec![Instruction::Jsr { pc_offset11: offset }.to_raw()])
+            }
+            Opcode::Ld | Opcode::Ldi | Opcode::Lea => {
+                let dr = self.register(0).with_position(position)?;
+                let pc_offset9 = self
+                    .pc_offset(1, labels, constants, fields::PC_OFFSET9)
+                    .with_position(position)?;
+                let instruction = match opcode {
+                    Opcode::Ld => Instruction::Ld { dr, pc_offset9 },
+                    Opcode::Ldi => Instruction::Ldi { dr, pc_offset9 },
+                    _ => Instruction::Lea { dr, pc_offset9 },
+                };
+                Ok(vec![instruction.to_raw()])
+            }
+            Opcode::St | Opcode::Sti => {
+                let sr = self.register(0).with_position(position)?;
+                let pc_offset9 = self
+                    .pc_offset(1, labels, constants, fields::PC_OFFSET9)
+                    .with_position(position)?;
+                let instruction = if opcode == Opcode::St {
+                    Instruction::St { sr, pc_offset9 }
+                } else {
+                    Instruction::Sti { sr, pc_offset9 }
+                };
+                Ok(vec![instruction.to_raw()])
+            }
+            Opcode::Ldr | Opcode::Str => {
+                let dr = self.register(0).with_position(position)?;
+                let base_r = self.register(1).with_position(position)?;
+                let offset = self.immediate(2, constants).with_position(position)?;
+                let offset6 = self
+                    .signed_field(offset, fields::OFFSET6)
+                    .with_position(position)?;
+                let instruction = if opcode == Opcode::Ldr {
+                    Instruction::Ldr { dr, base_r, offset6 }
+                } else {
+                    Instruction::Str { sr: dr, base_r, offset6 }
+                };
+                Ok(vec![instruction.to_raw()])
+            }
+            // NOP is a BR with no condition bits set and offset zero.
+            Opcode::Nop => {
+                if !self.operands.is_empty() {
+                    return Err(ErrorWithPosition::new(
+                        "'NOP' does not take operands",
+                        position,
+                    ));
+                }
+                Ok(vec![Instruction::Br { n: false, z: false, p: false, pc_offset9: 0 }.to_raw()])
+            }
+            // RET is JMP R7 and takes no operands of its own.
+            Opcode::Ret => {
+                if !self.operands.is_empty() {
+                    return Err(ErrorWithPosition::new(
+                        "'RET' does not take operands",
+                        position,
+                    ));
+                }
+                Ok(vec![Instruction::Jmp { base_r: Registers::R7 }.to_raw()])
+            }
+            Opcode::Rti => {
+                if !self.operands.is_empty() {
+                    return Err(ErrorWithPosition::new(
+                        "'RTI' does not take operands",
+                        position,
+                    ));
+                }
+                Ok(vec![Instruction::Rti {}.to_raw()])
+            }
+            Opcode::Not => {
+                let dr = self.register(0).with_position(position)?;
+                let sr = self.register(1).with_position(position)?;
+                Ok(vec![Instruction::Not { dr, sr }.to_raw()])
+            }
+            Opcode::Trap => {
+                // `TRAP GETC` parses the service name as a label; the
+                // well-known names map to their vectors before falling
+                // back to immediates and `.EQU` constants.
+                let vector = match self.operands.first() {
+                    Some(AstNode::Label { name, .. }) => match trap_vector(name) {
+                        Some(vector) => vector,
+                        None => match constants.get(*name) {
+                            Some(constant) => constant.value,
+                            None => {
+                                return Err(ErrorWithPosition::new(
+                                    format!(
+                                        "Unknown trap vector '{}' (expected GETC, OUT, \
+                                         PUTS, IN, PUTSP, HALT, a constant, or an immediate)",
+                                        name
+                                    ),
+                                    position,
+                                ));
+                            }
+                        },
+                    },
+                    _ => self.immediate(0, constants).with_position(position)?,
+                };
+                if vector > fields::mask(fields::TRAPVECT8) {
+                    return Err(ErrorWithPosition::new(
+                        format!("Trap vector {} is out of range [0, 255]", vector),
+                        position,
+                    ));
+                }
+                Ok(vec![Instruction::Trap { trapvect8: vector }.to_raw()])
+            }
+            Opcode::Getc | Opcode::Out | Opcode::Puts | Opcode::In | Opcode::Putsp
+            | Opcode::Halt => {
+                if !self.operands.is_empty() {
+                    return Err(ErrorWithPosition::new(
+                        format!("'{:?}' does not take operands", opcode),
+                        position,
+                    ));
+                }
+                let vector = match opcode {
+                    Opcode::Getc => 0x20,
+                    Opcode::Out => 0x21,
+                    Opcode::Puts => 0x22,
+                    Opcode::In => 0x23,
+                    Opcode::Putsp => 0x24,
+                    _ => 0x25,
+                };
+                Ok(vec![Instruction::Trap { trapvect8: vector }.to_raw()])
+            }
+            // `.FILL`, `.BLKW` and `.STRINGZ` live in the pseudo-op
+            // registry; these arms cover emittables built without one.
+            Opcode::Fill => FillOp
+                .emit(&self.operands, &self.context(labels, constants))
+                .with_position(position),
+            Opcode::Blkw => BlkwOp
+                .emit(&self.operands, &self.context(labels, constants))
+                .with_position(position),
+            Opcode::Stringz => StringzOp
+                .emit(&self.operands, &self.context(labels, constants))
+                .with_position(position),
+            // Packs two characters per word (low byte first) for PUTSP,
+            // padding an odd final character with a zero high byte.
+            Opcode::Stringzp => {
+                let mut chars = Vec::new();
+                for operand in &self.operands {
+                    match operand {
+                        AstNode::StringLiteral(text) => {
+                            chars.extend(decode_string(text).with_position(position)?.chars())
+                        }
+                        other => {
+                            return Err(ErrorWithPosition::new(
+                                format!("'.STRINGZP' expects string operands, got {:?}", other),
+                                position,
+                            ));
+                        }
+                    }
+                }
+                if chars.is_empty() {
+                    return Err(ErrorWithPosition::new(
+                        "'.STRINGZP' expects at least one string operand",
+                        position,
+                    ));
+                }
+                let mut words: Vec<u16> = chars
+                    .chunks(2)
+                    .map(|pair| {
+                        let low = pair[0] as u16;
+                        let high = pair.get(1).map(|c| *c as u16).unwrap_or(0);
+                        high << 8 | low
+                    })
+                    .collect();
+                words.push(0);
+                Ok(words)
+            }
+            // `.ASSERT` lowers to a checkpoint the VM recognizes: TRAP x7F
+            // followed by a descriptor (bit 15 set for a memory operand,
+            // register number otherwise), the memory address (or zero) and
+            // the expected value.
+            Opcode::Assert => {
+                let (descriptor, operand) = match self.operands.first() {
+                    Some(AstNode::RegisterOperand(register)) => (*register as u16, 0),
+                    Some(AstNode::Label { name, .. }) => {
+                        let address = self
+                            .label(labels, name)
+                            .map(|location| location.address)
+                            .ok_or_else(|| undefined_label(name, labels, constants))
+                            .with_position(position)?;
+                        (0x8000, address)
+                    }
+                    _ => {
+                        return Err(ErrorWithPosition::new(
+                            "'.ASSERT' expects a register or label followed by the expected value",
+                            position,
+                        ));
+                    }
+                };
+                let expected = self.immediate(1, constants).with_position(position)?;
+                Ok(vec![
+                    Instruction::Trap { trapvect8: 0x7F }.to_raw(),
+                    descriptor,
+                    operand,
+                    expected,
+                ])
+            }
+            opcode => Err(ErrorWithPosition::new(
+                format!("Opcode {:?} is not implemented by the emitter", opcode),
+                position,
+            )),
+        }
+    }
+
+    /// Range-checks a signed immediate against this instruction's field
+    /// width, naming the instruction in the error.
+    fn signed_field(&self, value: u16, bits: u16) -> Result<u16, String> {
+        let opcode = self.opcode.expect("only opcode arms range-check fields");
+        fields::encode(value as i16, bits)
+            .map_err(|message| format!("{:?}: {}", opcode, message))
+    }
+
+    fn register(&self, index: usize) -> Result<Registers, String> {
+        match &self.operands[index] {
+            AstNode::RegisterOperand(register) => {
+                Ok(Registers::from_u16_or_panic(*register as u16))
+            }
+            other => Err(format!("Expected a register operand, got {:?}", other)),
+        }
+    }
+
+    fn immediate(
+        &self,
+        index: usize,
+        constants: &HashMap<String, Constant>,
+    ) -> Result<u16, String> {
+        immediate_operand(&self.operands, index, constants)
+    }
+
+    fn pc_offset(
+        &self,
+        index: usize,
+        labels: &HashMap<String, MemoryLocation>,
+        constants: &HashMap<String, Constant>,
+        bits: u16,
+    ) -> Result<u16, String> {
+        let (name, target) = match &self.operands[index] {
+            AstNode::Label { name, .. } => (
+                *name,
+                self.label(labels, name)
+                    .map(|location| location.address)
+                    .ok_or_else(|| undefined_label(name, labels, constants))?,
+            ),
+            AstNode::AdjustedLabel { name, offset, .. } => (
+                *name,
+                self.label(labels, name)
+                    .map(|location| location.address.wrapping_add(*offset as u16))
+                    .ok_or_else(|| undefined_label(name, labels, constants))?,
+            ),
+            AstNode::ImmediateOperand(value) => {
+                return fields::encode(*value as i16, bits);
+            }
+            _ => {
+                let value = self.immediate(index, constants)?;
+                return fields::encode(value as i16, bits);
+            }
+        };
+        self.label_offset(name, target, bits)
+    }
+
+    /// Computes the PC-relative distance to `target` and range-checks it
+    /// against a `bits`-wide offset field. On overflow the error names the
+    /// label and states the computed distance so the user knows how far
+    /// over the limit they are.
+    fn label_offset(&self, name: &str, target: u16, bits: u16) -> Result<u16, String> {
+        // The incremented PC wraps at the top of memory, like the VM's.
+        let distance = target.wrapping_sub(self.address.wrapping_add(1)) as i16;
+        fields::encode(distance, bits).map_err(|_| {
+            format!(
+                "Label '{}' is {} instructions away, which does not fit into a \
+                 {}-bit offset (valid range is {} to {})",
+                name,
+                distance,
+                bits,
+                fields::min_value(bits),
+                fields::max_value(bits)
+            )
+        })
+    }
+}
+
+/// The vector behind a well-known trap service name, so `TRAP GETC` means
+/// the same as `TRAP x20`.
+fn trap_vector(name: &str) -> Option<u16> {
+    match name.to_ascii_uppercase().as_str() {
+        "GETC" => Some(0x20),
+        "OUT" => Some(0x21),
+        "PUTS" => Some(0x22),
+        "IN" => Some(0x23),
+        "PUTSP" => Some(0x24),
+        "HALT" => Some(0x25),
+        _ => None,
+    }
+}
+
+/// Builds the "never defined" error for a label reference, suggesting the
+/// closest defined label or constant when it looks like a typo.
+fn undefined_label(
+    name: &str,
+    labels: &HashMap<String, MemoryLocation>,
+    constants: &HashMap<String, Constant>,
+) -> String {
+    let mut message = format!("Label '{}' was never defined", name);
+    if let Some(suggestion) = crate::closest_label(name, labels, constants) {
+        message.push_str(&format!(", did you mean '{}'?", suggestion));
+    }
+    message
+}
+
+/// Decodes the backslash escapes allowed inside string literals: `\n`,
+/// `\t`, `\r`, `\0`, `\\`, `\"` and `\xNN`.
+pub fn decode_string(text: &str) -> Result<String, String> {
+    let mut chars = text.chars();
+    let mut result = String::with_capacity(text.len());
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            result.push(character);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some('\\') => result.push('\\'),
+            Some('"') => result.push('"'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let value = u8::from_str_radix(&digits, 16)
+                    .map_err(|_| format!("Invalid escape sequence '\\x{}'", digits))?;
+                result.push(value as char);
+            }
+            Some(other) => return Err(format!("Invalid escape sequence '\\{}'", other)),
+            None => return Err("Unterminated escape sequence at end of string".to_string()),
+        }
+    }
+    Ok(result)
+}
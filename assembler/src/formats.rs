@@ -0,0 +1,205 @@
+//! Intel HEX as an interchange format: [`to_ihex`] renders an [`Assembly`]
+//! into records, [`from_ihex`] parses them back into loadable segments.
+//! Word addresses are doubled into byte addresses on the way out (Intel HEX
+//! is byte-oriented) and halved on the way in.
+
+use crate::Assembly;
+
+/// Renders the assembly as Intel HEX records. The origin word is used as
+/// the base address and the program words are emitted big-endian in data
+/// records of up to eight words, followed by the end-of-file record.
+/// Word origins at x8000 and above double to byte addresses past 64 KiB,
+/// so a type-04 extended linear address record is emitted whenever the
+/// upper half of the byte address changes.
+pub fn to_ihex(assembly: &Assembly) -> String {
+    let origin = assembly.origin();
+    let words = assembly.words();
+
+    let mut output = String::new();
+    let mut upper = 0u16;
+    let mut index = 0;
+    while index < words.len() {
+        let byte_address = (origin as u32 + index as u32) * 2;
+        if (byte_address >> 16) as u16 != upper {
+            upper = (byte_address >> 16) as u16;
+            push_record(&mut output, &[2, 0, 0, 4, (upper >> 8) as u8, upper as u8]);
+        }
+        // Up to eight words per record, but never across a 64 KiB
+        // boundary, where the 16-bit record address would wrap.
+        let until_boundary = ((0x1_0000 - (byte_address & 0xFFFF)) / 2) as usize;
+        let count = (words.len() - index).min(8).min(until_boundary);
+        let mut bytes = vec![
+            (count * 2) as u8,
+            (byte_address >> 8) as u8,
+            byte_address as u8,
+            0,
+        ];
+        for word in &words[index..index + count] {
+            bytes.push((word >> 8) as u8);
+            bytes.push(*word as u8);
+        }
+        push_record(&mut output, &bytes);
+        index += count;
+    }
+    output.push_str(":00000001FF\n");
+    output
+}
+
+/// Renders one record: the colon, `bytes` in hex, and the checksum.
+fn push_record(output: &mut String, bytes: &[u8]) {
+    output.push(':');
+    for byte in bytes {
+        output.push_str(&format!("{:02X}", byte));
+    }
+    output.push_str(&format!("{:02X}\n", checksum(bytes)));
+}
+
+/// Parses Intel HEX text into `(word origin, words)` segments, merging
+/// contiguous data records. Checksums are verified and every malformed
+/// record is reported with its line number instead of loading garbage.
+pub fn from_ihex(text: &str) -> Result<Vec<(u16, Vec<u16>)>, String> {
+    let mut segments: Vec<(u16, Vec<u16>)> = Vec::new();
+    let mut saw_eof = false;
+    // The upper half of the byte address, set by type-04 extended linear
+    // address records.
+    let mut upper: u32 = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        if saw_eof {
+            return Err(format!(
+                "line {}: record after the end-of-file record",
+                line_number
+            ));
+        }
+        let Some(digits) = line.strip_prefix(':') else {
+            return Err(format!(
+                "line {}: record does not start with ':'",
+                line_number
+            ));
+        };
+        if !digits.len().is_multiple_of(2) {
+            return Err(format!(
+                "line {}: record has an odd number of hex digits",
+                line_number
+            ));
+        }
+        let bytes = (0..digits.len() / 2)
+            .map(|pair| u8::from_str_radix(&digits[pair * 2..pair * 2 + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("line {}: record contains a non-hex digit", line_number))?;
+        if bytes.len() < 5 {
+            return Err(format!("line {}: record is truncated", line_number));
+        }
+        let (data, trailer) = bytes.split_at(bytes.len() - 1);
+        let expected = checksum(data);
+        if trailer[0] != expected {
+            return Err(format!(
+                "line {}: checksum mismatch (expected {:02X}, found {:02X})",
+                line_number, expected, trailer[0]
+            ));
+        }
+        let count = bytes[0] as usize;
+        if bytes.len() != count + 5 {
+            return Err(format!(
+                "line {}: length field says {} data bytes but the record holds {}",
+                line_number,
+                count,
+                bytes.len() - 5
+            ));
+        }
+        match bytes[3] {
+            0x00 => {
+                let byte_address = upper << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+                if !byte_address.is_multiple_of(2) || !count.is_multiple_of(2) {
+                    return Err(format!(
+                        "line {}: data record is not 16-bit word aligned",
+                        line_number
+                    ));
+                }
+                let word_address = byte_address / 2;
+                if word_address > 0xFFFF {
+                    return Err(format!(
+                        "line {}: data record lies beyond the 16-bit word address space",
+                        line_number
+                    ));
+                }
+                let word_address = word_address as u16;
+                let words = bytes[4..4 + count]
+                    .chunks(2)
+                    .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16);
+                match segments.last_mut() {
+                    Some((origin, existing))
+                        if *origin as usize + existing.len() == word_address as usize =>
+                    {
+                        existing.extend(words)
+                    }
+                    _ => segments.push((word_address, words.collect())),
+                }
+            }
+            0x01 => saw_eof = true,
+            0x04 => {
+                if count != 2 {
+                    return Err(format!(
+                        "line {}: extended linear address record must carry two bytes",
+                        line_number
+                    ));
+                }
+                upper = (bytes[4] as u32) << 8 | bytes[5] as u32;
+            }
+            other => {
+                return Err(format!(
+                    "line {}: unsupported record type {:02X}",
+                    line_number, other
+                ));
+            }
+        }
+    }
+    if !saw_eof {
+        return Err("missing end-of-file record".to_string());
+    }
+    Ok(segments)
+}
+
+/// The magic prefix of the extended object format, distinguishing it from
+/// legacy images (whose first bytes are a big-endian origin word).
+pub const EXTENDED_OBJECT_MAGIC: &[u8; 4] = b"LC3X";
+
+/// Renders the assembly in the extended object format, which carries the
+/// symbol table alongside the program words so loaders can show label
+/// names. The layout, everything big-endian:
+///
+/// - the `LC3X` magic (4 bytes)
+/// - a version word (currently 1)
+/// - a segment count word, then per segment an origin word and a length
+///   word, then every segment's words in table order
+/// - a symbol count word, then per symbol an address word, a name byte
+///   length word and the UTF-8 name bytes
+pub fn to_extended_object(assembly: &Assembly) -> Vec<u8> {
+    let push_word = |bytes: &mut Vec<u8>, word: u16| bytes.extend_from_slice(&word.to_be_bytes());
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(EXTENDED_OBJECT_MAGIC);
+    push_word(&mut bytes, 1);
+    push_word(&mut bytes, 1); // a single segment, until sections land
+    push_word(&mut bytes, assembly.origin());
+    push_word(&mut bytes, assembly.words().len() as u16);
+    for word in assembly.words() {
+        push_word(&mut bytes, *word);
+    }
+    let symbols: Vec<_> = assembly.symbols().collect();
+    push_word(&mut bytes, symbols.len() as u16);
+    for (name, address) in symbols {
+        push_word(&mut bytes, address);
+        push_word(&mut bytes, name.len() as u16);
+        bytes.extend_from_slice(name.as_bytes());
+    }
+    bytes
+}
+
+pub(crate) fn checksum(bytes: &[u8]) -> u8 {
+    let sum: u32 = bytes.iter().map(|byte| *byte as u32).sum();
+    (sum as u8).wrapping_neg()
+}
@@ -0,0 +1,3595 @@
+//! An LC-3 assembler built on a [pest] grammar.
+//!
+//! The main entry point is [`assemble`], which turns LC-3 assembly source
+//! into an [`Assembly`] holding the emitted words (origin first), the label
+//! table and a map from memory addresses back to source positions.
+
+pub mod disassembler;
+pub mod emittable;
+pub mod formats;
+pub mod output;
+pub mod parser;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+
+use pest::iterators::Pair;
+use pest::{Position, Span};
+
+use emittable::Emittable;
+pub use emittable::{EmitContext, PseudoOp, PseudoOpRegistry};
+use parser::parse_collecting;
+pub use parser::{lex, parse, Lc3Parser, Rule, Token, TokenKind};
+
+/// A positioned assembler error. It owns everything it needs to render
+/// (line/column, the offending line's text, and the message), so it can
+/// outlive the source it was derived from; rendering still produces the
+/// familiar pest-style caret diagram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorWithPosition {
+    message: String,
+    line: usize,
+    column: usize,
+    line_text: String,
+    offset: usize,
+}
+
+impl ErrorWithPosition {
+    pub fn new<S: Into<String>>(message: S, position: Position<'_>) -> Self {
+        let (line, column) = position.line_col();
+        Self {
+            message: message.into(),
+            line,
+            column,
+            line_text: position.line_of().trim_end().to_string(),
+            offset: position.pos(),
+        }
+    }
+
+    pub fn from_parse_error(error: pest::error::Error<Rule>, source: &str) -> Self {
+        let offset = match error.location {
+            pest::error::InputLocation::Pos(pos) => pos,
+            pest::error::InputLocation::Span((start, _)) => start,
+        };
+        let position = Position::new(source, offset).unwrap_or_else(|| Position::from_start(source));
+        Self::new(error.variant.message().to_string(), position)
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// 1-based line number of the offending source position.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 1-based column number of the offending source position.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Byte offset into the source the error was derived from.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The same position with a different message, used to append macro
+    /// and include attribution.
+    fn with_message(self, message: String) -> Self {
+        Self { message, ..self }
+    }
+
+    /// The machine-readable form of this error, for tools that should not
+    /// have to parse the rendered caret diagram.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            line: self.line,
+            column: self.column,
+            end_line: self.line,
+            end_column: self.column,
+            message: self.message.clone(),
+            severity: Severity::Error,
+            code: diagnostic_code(&self.message, Severity::Error),
+        }
+    }
+}
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A structured diagnostic with 1-based line and column numbers, derived
+/// from pest's `Position`. Editor integrations consume these instead of
+/// the human-readable error strings. Assembler errors point at a single
+/// position, so the end coordinates currently equal the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub message: String,
+    pub severity: Severity,
+    /// A stable kebab-case category (e.g. `undefined-label`), so editors
+    /// can filter and document diagnostics without matching on message
+    /// text.
+    pub code: &'static str,
+}
+
+impl Diagnostic {
+    /// Renders this diagnostic as a JSON object. Hand-built, like the
+    /// other JSON in this crate, to keep serialization dependencies out.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"severity\": \"{}\", \"code\": \"{}\", \"line\": {}, \"column\": {}, \
+             \"endLine\": {}, \"endColumn\": {}, \"message\": \"{}\"}}",
+            match self.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            self.code,
+            self.line,
+            self.column,
+            self.end_line,
+            self.end_column,
+            escape_json(&self.message)
+        )
+    }
+}
+
+/// Renders a list of diagnostics as a JSON array, for `--json-diagnostics`.
+pub fn diagnostics_to_json(diagnostics: &[Diagnostic]) -> String {
+    let objects: Vec<String> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_json())
+        .collect();
+    format!("[{}]", objects.join(", "))
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            control if (control as u32) < 0x20 => {
+                escaped.push_str(&format!("\\u{:04x}", control as u32))
+            }
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// The coarse category for a diagnostic, derived from its message. The
+/// messages themselves are free to change wording; the codes stay stable.
+fn diagnostic_code(message: &str, severity: Severity) -> &'static str {
+    if message.contains("Unknown opcode") {
+        "unknown-opcode"
+    } else if message.contains("was never defined") || message.contains("Undefined constant") {
+        "undefined-label"
+    } else if message.contains("already defined") {
+        "duplicate-definition"
+    } else if message.contains("out of range") || message.contains("does not fit") {
+        "value-out-of-range"
+    } else if message.contains("overruns addressable memory") {
+        "program-too-large"
+    } else if message.contains(".ORIG") {
+        "missing-orig"
+    } else if message.contains("expects") || message.contains("does not take operands") {
+        "bad-operands"
+    } else if severity == Severity::Warning {
+        "warning"
+    } else {
+        "error"
+    }
+}
+
+impl fmt::Display for ErrorWithPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = self.line.to_string();
+        let spacing = " ".repeat(number.len());
+        writeln!(f, "{}--> {}:{}", spacing, self.line, self.column)?;
+        writeln!(f, "{} |", spacing)?;
+        writeln!(f, "{} | {}", number, self.line_text)?;
+        writeln!(f, "{} | {}^---", spacing, " ".repeat(self.column - 1))?;
+        writeln!(f, "{} |", spacing)?;
+        write!(f, "{} = {}", spacing, self.message)
+    }
+}
+
+impl std::error::Error for ErrorWithPosition {}
+
+/// Attaches a source position to a plain `Result<T, String>`, turning it
+/// into a `Result<T, ErrorWithPosition>`.
+pub trait PositionContext<T> {
+    fn with_position(self, position: Position<'_>) -> Result<T, ErrorWithPosition>;
+}
+
+impl<T> PositionContext<T> for Result<T, String> {
+    fn with_position(self, position: Position<'_>) -> Result<T, ErrorWithPosition> {
+        self.map_err(|message| ErrorWithPosition::new(message, position))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+}
+
+impl Register {
+    pub fn from_u8(value: u8) -> Result<Register, String> {
+        match value {
+            0 => Ok(Register::R0),
+            1 => Ok(Register::R1),
+            2 => Ok(Register::R2),
+            3 => Ok(Register::R3),
+            4 => Ok(Register::R4),
+            5 => Ok(Register::R5),
+            6 => Ok(Register::R6),
+            7 => Ok(Register::R7),
+            _ => Err(format!("Unknown register 'R{}'", value)),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(name: &str) -> Result<Register, String> {
+        // `R8` parses as a number but is out of range, and `R`/`RX` do not
+        // parse at all; all of them get the same teaching message.
+        let error = || format!("register must be R0–R7, got '{}'", name);
+        let number = name
+            .get(1..)
+            .filter(|digits| !digits.is_empty())
+            .ok_or_else(error)?
+            .parse::<u8>()
+            .map_err(|_| error())?;
+        Self::from_u8(number).map_err(|_| error())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Add,
+    And,
+    Br { n: bool, z: bool, p: bool },
+    Jmp,
+    Jsr,
+    Jsrr,
+    Ld,
+    Ldi,
+    Ldr,
+    Lea,
+    Not,
+    Ret,
+    Rti,
+    St,
+    Sti,
+    Str,
+    Trap,
+    Getc,
+    Out,
+    Puts,
+    In,
+    Putsp,
+    Halt,
+    Nop,
+    Fill,
+    Blkw,
+    Stringz,
+    Stringzp,
+    Assert,
+    Equ,
+    If,
+    Else,
+    Endif,
+}
+
+impl Opcode {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from(name: &str) -> Result<Opcode, String> {
+        let lower = name.to_lowercase();
+        let opcode = match lower.as_str() {
+            "add" => Opcode::Add,
+            "and" => Opcode::And,
+            "jmp" => Opcode::Jmp,
+            "jsr" => Opcode::Jsr,
+            "jsrr" => Opcode::Jsrr,
+            "ld" => Opcode::Ld,
+            "ldi" => Opcode::Ldi,
+            "ldr" => Opcode::Ldr,
+            "lea" => Opcode::Lea,
+            "not" => Opcode::Not,
+            "ret" => Opcode::Ret,
+            "rti" => Opcode::Rti,
+            "st" => Opcode::St,
+            "sti" => Opcode::Sti,
+            "str" => Opcode::Str,
+            "trap" => Opcode::Trap,
+            "getc" => Opcode::Getc,
+            "out" => Opcode::Out,
+            "puts" => Opcode::Puts,
+            "in" => Opcode::In,
+            "putsp" => Opcode::Putsp,
+            "halt" => Opcode::Halt,
+            "nop" => Opcode::Nop,
+            ".fill" => Opcode::Fill,
+            ".blkw" => Opcode::Blkw,
+            ".stringz" => Opcode::Stringz,
+            // `.STRINGP` is the lc3tools spelling of the same packing.
+            ".stringzp" | ".stringp" => Opcode::Stringzp,
+            ".assert" => Opcode::Assert,
+            ".equ" | ".set" => Opcode::Equ,
+            ".if" => Opcode::If,
+            ".else" => Opcode::Else,
+            ".endif" => Opcode::Endif,
+            flags if flags.starts_with("br") => {
+                let flags = &flags[2..];
+                if flags.is_empty() {
+                    Opcode::Br {
+                        n: true,
+                        z: true,
+                        p: true,
+                    }
+                } else {
+                    Opcode::Br {
+                        n: flags.contains('n'),
+                        z: flags.contains('z'),
+                        p: flags.contains('p'),
+                    }
+                }
+            }
+            _ => {
+                let mut message = format!("Unknown opcode '{}'", lower);
+                if let Some(suggestion) = closest_opcode(&lower) {
+                    message.push_str(&format!(", did you mean '{}'?", suggestion));
+                }
+                return Err(message);
+            }
+        };
+        Ok(opcode)
+    }
+
+    /// The canonical spelling used in diagnostics.
+    pub fn mnemonic(&self) -> String {
+        match self {
+            Opcode::Br { .. } => "BR".to_string(),
+            Opcode::Fill => ".FILL".to_string(),
+            Opcode::Blkw => ".BLKW".to_string(),
+            Opcode::Stringz => ".STRINGZ".to_string(),
+            Opcode::Stringzp => ".STRINGZP".to_string(),
+            Opcode::Assert => ".ASSERT".to_string(),
+            Opcode::Equ => ".EQU".to_string(),
+            Opcode::If => ".IF".to_string(),
+            Opcode::Else => ".ELSE".to_string(),
+            Opcode::Endif => ".ENDIF".to_string(),
+            other => format!("{:?}", other).to_uppercase(),
+        }
+    }
+
+    fn signature(&self) -> Signature {
+        use OperandKind::*;
+        match self {
+            Opcode::Add | Opcode::And => Signature::new(&[Register, Register, RegisterOrValue]),
+            Opcode::Br { .. } | Opcode::Jsr | Opcode::Trap | Opcode::Fill => {
+                Signature::new(&[Value])
+            }
+            Opcode::Jmp | Opcode::Jsrr => Signature::new(&[Register]),
+            Opcode::Ld | Opcode::Ldi | Opcode::Lea | Opcode::St | Opcode::Sti => {
+                Signature::new(&[Register, Value])
+            }
+            Opcode::Ldr | Opcode::Str => Signature::new(&[Register, Register, Value]),
+            Opcode::Not => Signature::new(&[Register, Register]),
+            Opcode::Ret
+            | Opcode::Rti
+            | Opcode::Nop
+            | Opcode::Getc
+            | Opcode::Out
+            | Opcode::Puts
+            | Opcode::In
+            | Opcode::Putsp
+            | Opcode::Halt => Signature::new(&[]),
+            Opcode::Blkw => Signature::new(&[Value]).with_optional(&[Value]),
+            Opcode::Stringz | Opcode::Stringzp => Signature::new(&[String]).with_rest(String),
+            Opcode::Assert => {
+                Signature::new(&[RegisterOrLabel, Value]).with_optional(&[String])
+            }
+            Opcode::Equ => Signature::new(&[Label, Value]),
+            Opcode::If => Signature::new(&[Label]),
+            Opcode::Else | Opcode::Endif => Signature::new(&[]),
+        }
+    }
+
+    /// Checks the operand list against this opcode's signature, so mistakes
+    /// like `ADD #1, #1` or a missing `NOT` operand are caught with a clear
+    /// message instead of failing deep inside emission.
+    pub fn validate_operands(&self, operands: &[AstNode<'_>]) -> Result<(), String> {
+        let signature = self.signature();
+        let min = signature.required.len();
+        let max = min + signature.optional.len();
+        // A mistyped operand is reported before a wrong count, so
+        // `ADD #1, #1` complains about the immediate rather than the
+        // missing third operand.
+        for (index, operand) in operands.iter().enumerate() {
+            let kind = if index < min {
+                signature.required[index]
+            } else if index < max {
+                signature.optional[index - min]
+            } else if let Some(rest) = signature.rest {
+                rest
+            } else {
+                break;
+            };
+            if !kind.matches(operand) {
+                return Err(format!(
+                    "'{}' expects {} as {} operand, got {}",
+                    self.mnemonic(),
+                    kind.description(),
+                    ordinal(index),
+                    operand_description(operand)
+                ));
+            }
+        }
+        if operands.len() < min || (signature.rest.is_none() && operands.len() > max) {
+            if max == 0 {
+                return Err(format!(
+                    "'{}' does not take operands, got {}",
+                    self.mnemonic(),
+                    operands.len()
+                ));
+            }
+            let expected = if signature.rest.is_some() {
+                format!("at least {}", min)
+            } else if max > min {
+                format!("{} to {}", min, max)
+            } else {
+                min.to_string()
+            };
+            return Err(format!(
+                "'{}' expects {} operand{}, got {} (usage: {})",
+                self.mnemonic(),
+                expected,
+                if max == 1 { "" } else { "s" },
+                operands.len(),
+                signature.render(&self.mnemonic())
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Every opcode spelling `Opcode::from` accepts, used for typo suggestions.
+const KNOWN_OPCODES: &[&str] = &[
+    "ADD", "AND", "BR", "JMP", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "NOT", "RET", "RTI",
+    "ST", "STI", "STR", "TRAP", "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT", "NOP", ".FILL",
+    ".BLKW", ".STRINGZ", ".STRINGZP", ".STRINGP", ".ASSERT", ".EQU", ".SET", ".IF", ".ELSE",
+    ".ENDIF",
+];
+
+/// The known opcode closest to `name` (by Levenshtein distance), if it is
+/// close enough to plausibly be a typo.
+fn closest_opcode(name: &str) -> Option<&'static str> {
+    KNOWN_OPCODES
+        .iter()
+        .map(|candidate| (levenshtein(name, &candidate.to_lowercase()), *candidate))
+        .min()
+        .filter(|(distance, _)| *distance <= 2)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The defined label or constant closest to `name` (by Levenshtein
+/// distance), if it is close enough to plausibly be a typo.
+pub(crate) fn closest_label<'a>(
+    name: &str,
+    labels: &'a HashMap<String, MemoryLocation>,
+    constants: &'a HashMap<String, Constant>,
+) -> Option<&'a str> {
+    labels
+        .keys()
+        .chain(constants.keys())
+        .map(|candidate| (levenshtein(name, candidate), candidate.as_str()))
+        .min()
+        .filter(|(distance, _)| *distance <= 2)
+        .map(|(_, candidate)| candidate)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + (a_char != *b_char) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b_chars.len()]
+}
+
+/// The operand categories an opcode signature is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OperandKind {
+    Register,
+    /// A label, adjusted label or immediate; anything that resolves to a
+    /// 16-bit value.
+    Value,
+    RegisterOrValue,
+    RegisterOrLabel,
+    Label,
+    String,
+}
+
+impl OperandKind {
+    fn matches(&self, node: &AstNode<'_>) -> bool {
+        match self {
+            OperandKind::Register => matches!(node, AstNode::RegisterOperand(_)),
+            OperandKind::Value => matches!(
+                node,
+                AstNode::ImmediateOperand(_)
+                    | AstNode::Label { .. }
+                    | AstNode::AdjustedLabel { .. }
+            ),
+            OperandKind::RegisterOrValue => {
+                OperandKind::Register.matches(node) || OperandKind::Value.matches(node)
+            }
+            OperandKind::RegisterOrLabel => matches!(
+                node,
+                AstNode::RegisterOperand(_) | AstNode::Label { .. }
+            ),
+            OperandKind::Label => matches!(node, AstNode::Label { .. }),
+            OperandKind::String => matches!(node, AstNode::StringLiteral(_)),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            OperandKind::Register => "a register",
+            OperandKind::Value => "a label or immediate",
+            OperandKind::RegisterOrValue => "a register or immediate",
+            OperandKind::RegisterOrLabel => "a register or label",
+            OperandKind::Label => "a label",
+            OperandKind::String => "a string",
+        }
+    }
+
+    /// The placeholder used when rendering a whole signature.
+    fn token(&self) -> &'static str {
+        match self {
+            OperandKind::Register => "<register>",
+            OperandKind::Value => "<label|immediate>",
+            OperandKind::RegisterOrValue => "<register|immediate>",
+            OperandKind::RegisterOrLabel => "<register|label>",
+            OperandKind::Label => "<name>",
+            OperandKind::String => "<string>",
+        }
+    }
+}
+
+/// An opcode's operand signature: required kinds, optional trailing kinds
+/// and (for the string directives) a kind that may repeat indefinitely.
+struct Signature {
+    required: &'static [OperandKind],
+    optional: &'static [OperandKind],
+    rest: Option<OperandKind>,
+}
+
+impl Signature {
+    fn new(required: &'static [OperandKind]) -> Self {
+        Self {
+            required,
+            optional: &[],
+            rest: None,
+        }
+    }
+
+    fn with_optional(mut self, optional: &'static [OperandKind]) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    fn with_rest(mut self, rest: OperandKind) -> Self {
+        self.rest = Some(rest);
+        self
+    }
+
+    /// The usage line quoted in wrong-arity diagnostics, e.g.
+    /// `ADD <register>, <register>, <register|immediate>`.
+    fn render(&self, mnemonic: &str) -> String {
+        let mut parts: Vec<String> = self
+            .required
+            .iter()
+            .map(|kind| kind.token().to_string())
+            .collect();
+        parts.extend(self.optional.iter().map(|kind| format!("[{}]", kind.token())));
+        if let Some(rest) = self.rest {
+            parts.push(format!("[{}...]", rest.token()));
+        }
+        if parts.is_empty() {
+            mnemonic.to_string()
+        } else {
+            format!("{} {}", mnemonic, parts.join(", "))
+        }
+    }
+}
+
+fn ordinal(index: usize) -> String {
+    match index {
+        0 => "first".to_string(),
+        1 => "second".to_string(),
+        2 => "third".to_string(),
+        3 => "fourth".to_string(),
+        _ => format!("{}th", index + 1),
+    }
+}
+
+fn operand_description(node: &AstNode<'_>) -> String {
+    match node {
+        AstNode::RegisterOperand(register) => format!("register '{:?}'", register),
+        AstNode::ImmediateOperand(value) => format!("immediate '#{}'", *value as i16),
+        AstNode::Label { name, .. } => format!("label '{}'", name),
+        AstNode::AdjustedLabel { name, offset, .. } => {
+            format!("label '{}{}{}'", name, if *offset < 0 { "" } else { "+" }, offset)
+        }
+        AstNode::StringLiteral(text) => format!("string \"{}\"", text),
+        _ => "operand".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstNode<'a> {
+    SectionScope {
+        /// `None` when the section had no `.ORIG` directive; the emitter
+        /// decides whether that is a warning or an error.
+        origin: Option<u16>,
+        content: Vec<AstNode<'a>>,
+        span: Span<'a>,
+    },
+    Line {
+        label: Option<Box<AstNode<'a>>>,
+        instruction: Option<Box<AstNode<'a>>>,
+    },
+    Instruction {
+        opcode: Opcode,
+        operands: Vec<AstNode<'a>>,
+        span: Span<'a>,
+    },
+    /// A directive the grammar does not know, resolved against the
+    /// pseudo-op registry at emission time so library users can register
+    /// their own without forking the `Opcode` enum.
+    PseudoInstruction {
+        name: &'a str,
+        operands: Vec<AstNode<'a>>,
+        span: Span<'a>,
+    },
+    Label {
+        name: &'a str,
+        span: Span<'a>,
+    },
+    /// A label operand with a constant adjustment, e.g. `TABLE+1`.
+    AdjustedLabel {
+        name: &'a str,
+        offset: i16,
+        span: Span<'a>,
+    },
+    RegisterOperand(Register),
+    ImmediateOperand(u16),
+    StringLiteral(&'a str),
+}
+
+/// The address a label resolved to during the first emitter pass, plus the
+/// byte offset of its definition so redefinitions can point at the original
+/// site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryLocation {
+    pub address: u16,
+    pub position: usize,
+}
+
+/// A symbolic constant defined via `.EQU`/`.SET`, remembering where it was
+/// defined so redefinitions can point at the original site.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Constant {
+    pub value: u16,
+    pub position: usize,
+}
+
+/// The result of a successful assembly run. `data()` starts with the origin
+/// word, followed by the emitted program words.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Assembly {
+    data: Vec<u16>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted_map"))]
+    labels: HashMap<String, MemoryLocation>,
+    // The same labels keyed by address, so nearest-label lookups are
+    // O(log n) instead of a scan over the HashMap.
+    labels_by_address: BTreeMap<u16, String>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted_map"))]
+    constants: HashMap<String, Constant>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted_map"))]
+    source_map: HashMap<u16, usize>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted_map"))]
+    assertions: HashMap<u16, String>,
+    // Warnings carry `&'static` diagnostic codes and are not part of a
+    // cached artifact; they reset to empty on deserialization.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    warnings: Vec<Diagnostic>,
+    case_insensitive_labels: bool,
+}
+
+/// Serializes a `HashMap` with its keys sorted, so the JSON produced by
+/// [`Assembly::to_json`] is deterministic across runs.
+#[cfg(feature = "serde")]
+fn sorted_map<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Ord + serde::Serialize,
+    V: serde::Serialize,
+    S: serde::Serializer,
+{
+    use serde::Serialize;
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl Assembly {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn data(&self) -> &[u16] {
+        &self.data
+    }
+
+    /// The address the program was assembled at.
+    pub fn origin(&self) -> u16 {
+        self.data.first().copied().unwrap_or(0)
+    }
+
+    /// The program words without the leading origin word; the natural
+    /// input to the VM's `load_words`.
+    pub fn words(&self) -> &[u16] {
+        self.data.get(1..).unwrap_or(&[])
+    }
+
+    /// The program placed into a full memory image at its origin, plus the
+    /// entry origin, so embedders can copy memory in wholesale instead of
+    /// re-implementing the origin-prefix convention of [`data`]. Once
+    /// multiple sections are supported each will land at its own origin.
+    ///
+    /// [`data`]: Assembly::data
+    pub fn into_memory_image(&self) -> ([u16; MEM_SIZE], u16) {
+        let mut image = [0u16; MEM_SIZE];
+        let origin = self.origin();
+        let words = self.words();
+        image[origin as usize..origin as usize + words.len()].copy_from_slice(words);
+        (image, origin)
+    }
+
+    /// Maps memory addresses to byte offsets into the assembled source.
+    pub fn source_map(&self) -> &HashMap<u16, usize> {
+        &self.source_map
+    }
+
+    /// [`source_lines`] reduced to just the 1-based line number, which is
+    /// all a debugger gutter needs.
+    ///
+    /// [`source_lines`]: Assembly::source_lines
+    pub fn line_map(&self, source: &str) -> HashMap<u16, usize> {
+        self.source_lines(source)
+            .into_iter()
+            .map(|(address, (line, _))| (address, line))
+            .collect()
+    }
+
+    /// [`source_map`] resolved against the source text: maps each emitted
+    /// address to a 1-based `(line, column)` pair, so a debugger can
+    /// highlight the source line for a given PC without re-deriving
+    /// positions itself. `source` must be the text that was assembled.
+    ///
+    /// [`source_map`]: Assembly::source_map
+    pub fn source_lines(&self, source: &str) -> HashMap<u16, (usize, usize)> {
+        self.source_map
+            .iter()
+            .filter_map(|(address, offset)| {
+                let position = Position::new(source, *offset)?;
+                Some((*address, position.line_col()))
+            })
+            .collect()
+    }
+
+    /// The label table, mapping each label to the address it resolved to
+    /// (plus where it was defined). For just the address, see
+    /// [`address_of`].
+    ///
+    /// [`address_of`]: Assembly::address_of
+    pub fn labels(&self) -> &HashMap<String, MemoryLocation> {
+        &self.labels
+    }
+
+    /// The address `label` resolved to, if it was defined.
+    pub fn address_of(&self, label: &str) -> Option<u16> {
+        self.labels.get(label).map(|location| location.address)
+    }
+
+    /// The address the symbol `name` resolved to; an alias for
+    /// [`address_of`] under the name debugger front ends look for.
+    ///
+    /// [`address_of`]: Assembly::address_of
+    pub fn symbol(&self, name: &str) -> Option<u16> {
+        self.address_of(name)
+    }
+
+    /// The nearest label at or before `address`, which is how a debugger
+    /// annotates an arbitrary address as `LOOP+2`.
+    pub fn symbol_at(&self, address: u16) -> Option<&str> {
+        self.labels_by_address
+            .range(..=address)
+            .next_back()
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// All symbols sorted by address.
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.labels_by_address
+            .iter()
+            .map(|(address, name)| (name.as_str(), *address))
+    }
+
+    /// Messages attached to `.ASSERT` checkpoints, keyed by the checkpoint's
+    /// address. The message is debug information only; it does not appear in
+    /// the emitted words.
+    pub fn assertions(&self) -> &HashMap<u16, String> {
+        &self.assertions
+    }
+
+    /// Non-fatal diagnostics produced while assembling, such as a missing
+    /// `.ORIG` that was papered over with the default origin.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Writes the label table in the lc3tools `.sym` text format: the
+    /// standard comment header followed by one name/address pair per line,
+    /// sorted by address.
+    pub fn write_symbol_file(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        writeln!(writer, "// Symbol table")?;
+        writeln!(writer, "// Scope level 0:")?;
+        writeln!(writer, "//\tSymbol Name       Page Address")?;
+        writeln!(writer, "//\t----------------  ------------")?;
+        let mut labels: Vec<_> = self.labels.iter().collect();
+        labels.sort_by_key(|(name, location)| (location.address, name.as_str()));
+        for (name, location) in labels {
+            writeln!(writer, "//\t{:<16}  {:04X}", name, location.address)?;
+        }
+        Ok(())
+    }
+
+    /// Renders a classic listing: one row per emitted word showing its
+    /// address, the word in hex, and the source line it came from (via
+    /// [`source_map`]). Words after the first of a multi-word emittable
+    /// such as `.STRINGZ` carry a continuation marker instead of repeating
+    /// the line.
+    ///
+    /// [`source_map`]: Assembly::source_map
+    pub fn to_listing(&self, source: &str) -> String {
+        let origin = self.origin();
+        let mut output = String::new();
+        for (index, word) in self.words().iter().enumerate() {
+            let address = origin + index as u16;
+            let row = match self.source_map.get(&address) {
+                Some(offset) => {
+                    let line_index = source[..*offset].matches('\n').count();
+                    source.lines().nth(line_index).unwrap_or("").trim_end()
+                }
+                None => "..",
+            };
+            output.push_str(&format!("x{:04X}  x{:04X}  {}\n", address, word, row));
+        }
+        output
+    }
+
+    /// Writes the extended object format: the program words plus a segment
+    /// table and the symbol table, so loaders can show label names next to
+    /// addresses. See [`formats::to_extended_object`] for the layout.
+    pub fn write_extended_object(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        writer.write_all(&formats::to_extended_object(self))
+    }
+
+    /// Renders the assembly as Intel HEX records. The origin word is used as
+    /// the base address (in bytes, since Intel HEX is byte-oriented) and the
+    /// program words are emitted big-endian in data records of up to eight
+    /// words, followed by the end-of-file record.
+    pub fn to_intel_hex(&self) -> String {
+        formats::to_ihex(self)
+    }
+
+    /// Renders a plain hex dump: one four-digit uppercase hex word per
+    /// line, with the origin as the first record.
+    pub fn to_hex_dump(&self) -> String {
+        self.data
+            .iter()
+            .map(|word| format!("{:04X}\n", word))
+            .collect()
+    }
+
+    /// Renders the textual `.bin` format: sixteen ASCII 0/1 characters per
+    /// line, with the origin as the first record.
+    pub fn to_bin_text(&self) -> String {
+        self.data
+            .iter()
+            .map(|word| format!("{:016b}\n", word))
+            .collect()
+    }
+
+    /// Renders the program as a C array for embedding. The origin is noted
+    /// in a comment; the array holds only the program words.
+    pub fn to_c_array(&self) -> String {
+        let words = self.words();
+        let mut output = format!(
+            "/* .ORIG x{:04X} */\nconst unsigned short program[{}] = {{\n",
+            self.origin(),
+            words.len()
+        );
+        for chunk in words.chunks(8) {
+            let row: Vec<String> = chunk.iter().map(|word| format!("0x{:04X}", word)).collect();
+            output.push_str(&format!("    {},\n", row.join(", ")));
+        }
+        output.push_str("};\n");
+        output
+    }
+
+    /// Serializes this assembly to JSON for caching between runs. Map keys
+    /// are sorted, so identical assemblies produce identical strings.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("an Assembly always serializes")
+    }
+
+    /// Deserializes an assembly previously produced by [`to_json`].
+    ///
+    /// [`to_json`]: Assembly::to_json
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|error| error.to_string())
+    }
+
+    fn record_label(&mut self, name: &str, address: u16, span: &Span) -> Result<(), String> {
+        // With case-insensitive labels the folded name is the key, so
+        // `loop` and `LOOP` collide here instead of shadowing each other.
+        let name = if self.case_insensitive_labels {
+            name.to_ascii_uppercase()
+        } else {
+            name.to_string()
+        };
+        if let Some(previous) = self.labels.get(&name) {
+            let line = Position::new(span.get_input(), previous.position)
+                .map(|position| position.line_col().0)
+                .unwrap_or(0);
+            return Err(format!(
+                "Label '{}' is already defined at line {}",
+                name, line
+            ));
+        }
+        // The first label recorded for an address wins the reverse mapping,
+        // so aliases do not churn `symbol_at` results.
+        self.labels_by_address
+            .entry(address)
+            .or_insert_with(|| name.clone());
+        self.labels.insert(
+            name,
+            MemoryLocation {
+                address,
+                position: span.start(),
+            },
+        );
+        Ok(())
+    }
+
+    fn record_constant(&mut self, name: &str, value: u16, span: &Span) -> Result<(), String> {
+        if let Some(previous) = self.constants.get(name) {
+            let line = Position::new(span.get_input(), previous.position)
+                .map(|position| position.line_col().0)
+                .unwrap_or(0);
+            return Err(format!(
+                "Constant '{}' is already defined at line {}",
+                name, line
+            ));
+        }
+        self.constants.insert(
+            name.to_string(),
+            Constant {
+                value,
+                position: span.start(),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// The origin assumed for sections without an explicit `.ORIG`, matching
+/// the classroom convention.
+pub const DEFAULT_ORIGIN: u16 = 0x3000;
+
+/// The number of words in the LC-3 address space.
+pub const MEM_SIZE: usize = 0x10000;
+
+/// Knobs for [`assemble_with_options`]. The `Default` values reproduce the
+/// behavior of plain [`assemble`].
+#[derive(Debug, Clone)]
+pub struct AssembleOptions {
+    /// The origin used for a section without `.ORIG`: `Some(address)`
+    /// assembles it there with a warning, `None` rejects it with an error.
+    /// The playground relocates pasted snippets by overriding this.
+    pub default_origin: Option<u16>,
+    /// Treat warnings as errors.
+    pub strict: bool,
+    /// Fold label definitions and references to upper case before matching,
+    /// so `loop` and `LOOP` name the same address. `.EQU` constants stay
+    /// case-sensitive.
+    pub case_insensitive_labels: bool,
+    /// Handlers for pseudo-ops, consulted by mnemonic during emission. The
+    /// default registry holds the built-in directives; additional ones can
+    /// be registered via [`with_pseudo_op`](AssembleOptions::with_pseudo_op).
+    pub pseudo_ops: PseudoOpRegistry,
+}
+
+impl Default for AssembleOptions {
+    fn default() -> Self {
+        Self {
+            default_origin: Some(DEFAULT_ORIGIN),
+            strict: false,
+            case_insensitive_labels: false,
+            pseudo_ops: PseudoOpRegistry::with_builtins(),
+        }
+    }
+}
+
+impl AssembleOptions {
+    /// Registers a [`PseudoOp`] handler for the directive `name` (e.g.
+    /// `".WORDSWAP"`), replacing any previous handler of that name.
+    pub fn with_pseudo_op(mut self, name: &str, handler: std::rc::Rc<dyn PseudoOp>) -> Self {
+        self.pseudo_ops.register(name, handler);
+        self
+    }
+}
+
+/// Emits a single `SectionScope` into the given assembly. Pass one records
+/// labels and `.EQU` constants and sizes every emittable; pass two resolves
+/// operands and produces the final words.
+pub fn emit_section<'a>(
+    section: &AstNode<'a>,
+    assembly: &mut Assembly,
+) -> Result<(), ErrorWithPosition> {
+    let mut errors = Vec::new();
+    emit_section_collecting(section, assembly, &AssembleOptions::default(), &mut errors);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// The error-collecting core of [`emit_section`]: instead of stopping at
+/// the first problem, every positioned error is pushed onto `errors` and
+/// emission continues (erroneous emittables leave zeroed words behind so
+/// later addresses stay correct).
+fn emit_section_collecting<'a>(
+    section: &AstNode<'a>,
+    assembly: &mut Assembly,
+    options: &AssembleOptions,
+    errors: &mut Vec<ErrorWithPosition>,
+) {
+    let AstNode::SectionScope {
+        origin,
+        content,
+        span,
+    } = section
+    else {
+        unreachable!("emit_section must be called with a SectionScope node");
+    };
+
+    let origin = match origin {
+        Some(origin) => *origin,
+        None => match options.default_origin {
+            Some(default) => {
+                let message = format!("No '.ORIG' directive; defaulting to x{:04X}", default);
+                if options.strict {
+                    errors.push(ErrorWithPosition::new(message, span.start_pos()));
+                } else {
+                    let (line, column) = span.start_pos().line_col();
+                    assembly.warnings.push(Diagnostic {
+                        line,
+                        column,
+                        end_line: line,
+                        end_column: column,
+                        code: diagnostic_code(&message, Severity::Warning),
+                        message,
+                        severity: Severity::Warning,
+                    });
+                }
+                default
+            }
+            None => {
+                errors.push(ErrorWithPosition::new(
+                    "Missing '.ORIG' directive",
+                    span.start_pos(),
+                ));
+                // Emission continues at the default so later errors still
+                // point at sensible addresses.
+                DEFAULT_ORIGIN
+            }
+        },
+    };
+
+    assembly.data.push(origin);
+
+    let mut offset = origin;
+    let mut emittables = Vec::new();
+    for line in content {
+        let AstNode::Line { label, instruction } = line else {
+            continue;
+        };
+        if let Some(label_node) = label {
+            let AstNode::Label { name, span } = &**label_node else {
+                unreachable!("Line labels are always Label nodes");
+            };
+            if let Err(error) = assembly
+                .record_label(name, offset, span)
+                .with_position(span.start_pos())
+            {
+                errors.push(error);
+            }
+        }
+        let Some(instruction_node) = instruction else {
+            continue;
+        };
+        // Known opcodes may still be routed through the registry (so a
+        // handler can replace a built-in directive); registry-only
+        // directives must be found there or they are unknown opcodes.
+        let (opcode, operands, span, handler) = match &**instruction_node {
+            AstNode::Instruction {
+                opcode,
+                operands,
+                span,
+            } => (
+                Some(*opcode),
+                operands,
+                span,
+                options.pseudo_ops.get(&opcode.mnemonic()).cloned(),
+            ),
+            AstNode::PseudoInstruction {
+                name,
+                operands,
+                span,
+            } => match options.pseudo_ops.get(name).cloned() {
+                Some(handler) => (None, operands, span, Some(handler)),
+                None => {
+                    let message = match Opcode::from(name) {
+                        Err(message) => message,
+                        Ok(_) => format!("Unknown opcode '{}'", name.to_lowercase()),
+                    };
+                    errors.push(ErrorWithPosition::new(message, span.start_pos()));
+                    continue;
+                }
+            },
+            _ => unreachable!("Line instructions are always Instruction nodes"),
+        };
+
+        if opcode == Some(Opcode::Equ) {
+            if let Err(error) = record_equ_constant(assembly, operands, span) {
+                errors.push(error);
+            }
+            continue;
+        }
+
+        if opcode == Some(Opcode::Assert) {
+            if let Some(AstNode::StringLiteral(message)) = operands.get(2) {
+                assembly.assertions.insert(offset, message.to_string());
+            }
+        }
+
+        let mut emittable = match opcode {
+            Some(opcode) => Emittable::from(opcode, operands.clone(), offset, *span),
+            None => Emittable::pseudo(operands.clone(), offset, *span),
+        }
+        .with_case_insensitive_labels(options.case_insensitive_labels);
+        if let Some(handler) = handler {
+            emittable = emittable.with_pseudo_op(handler);
+        }
+        let size = emittable.size(&assembly.constants);
+        let end = offset as u32 + size as u32;
+        if end > MEM_SIZE as u32 {
+            // Addresses past this line would wrap around to x0000; every
+            // later label and offset would be garbage, so stop sizing here.
+            errors.push(ErrorWithPosition::new(
+                format!(
+                    "Program overruns addressable memory: this line would end at \
+                     x{:05X}, past the last address xFFFF",
+                    end - 1
+                ),
+                span.start_pos(),
+            ));
+            break;
+        }
+        offset = end as u16;
+        emittables.push((emittable, size));
+    }
+
+    // Pass two resolves operands against the complete label and constant
+    // tables; `size` is what pass one laid the addresses out with.
+    for (emittable, size) in emittables {
+        assembly
+            .source_map
+            .insert(emittable.address(), emittable.span().start());
+        match emittable.emit(&assembly.labels, &assembly.constants) {
+            Ok(words) => {
+                // A line that emits a different number of words than pass
+                // one sized it at would silently shift every later
+                // address, so a mismatch is a hard error.
+                if words.len() != size as usize {
+                    errors.push(ErrorWithPosition::new(
+                        format!(
+                            "This line emitted {} words but was sized as {}; every \
+                             address after it would be wrong (a count constant must \
+                             be defined before its first use)",
+                            words.len(),
+                            size
+                        ),
+                        emittable.span().start_pos(),
+                    ));
+                }
+                assembly.data.extend(words);
+            }
+            Err(error) => {
+                errors.push(error);
+                assembly
+                    .data
+                    .extend(std::iter::repeat_n(0, size as usize));
+            }
+        }
+    }
+}
+
+fn record_equ_constant<'a>(
+    assembly: &mut Assembly,
+    operands: &[AstNode<'a>],
+    span: &Span<'a>,
+) -> Result<(), ErrorWithPosition> {
+    let name = match operands.first() {
+        Some(AstNode::Label { name, .. }) => *name,
+        _ => {
+            return Err(ErrorWithPosition::new(
+                "'.EQU' expects a constant name as its first operand",
+                span.start_pos(),
+            ))
+        }
+    };
+    let value = match operands.get(1) {
+        Some(AstNode::ImmediateOperand(value)) => *value,
+        _ => {
+            return Err(ErrorWithPosition::new(
+                "'.EQU' expects an immediate value as its second operand",
+                span.start_pos(),
+            ))
+        }
+    };
+    assembly
+        .record_constant(name, value, span)
+        .with_position(span.start_pos())
+}
+
+/// Resolves `.IF symbol` / `.ELSE` / `.ENDIF` blocks in a section before
+/// emission, keeping only the lines of active branches. A symbol is truthy
+/// when an earlier active `.EQU` defines it with a nonzero value; an
+/// undefined symbol is false, so the same file assembles both ways
+/// depending on whether a variant defines it.
+fn apply_conditionals<'a>(section: &AstNode<'a>) -> Result<AstNode<'a>, ErrorWithPosition> {
+    let AstNode::SectionScope {
+        origin,
+        content,
+        span,
+    } = section
+    else {
+        unreachable!("apply_conditionals must be called with a SectionScope node");
+    };
+
+    // One frame per open `.IF`: the truth of its condition, whether the
+    // `.ELSE` has been passed, and where it started for error reporting.
+    struct Frame<'s> {
+        condition: bool,
+        seen_else: bool,
+        span: Span<'s>,
+    }
+    let active = |stack: &[Frame]| {
+        stack
+            .iter()
+            .all(|frame| frame.condition != frame.seen_else)
+    };
+
+    let mut constants: HashMap<&str, u16> = HashMap::new();
+    let mut stack: Vec<Frame<'a>> = Vec::new();
+    let mut kept = Vec::new();
+
+    for line in content {
+        let instruction = match line {
+            AstNode::Line {
+                instruction: Some(instruction),
+                ..
+            } => match &**instruction {
+                AstNode::Instruction {
+                    opcode,
+                    operands,
+                    span,
+                } => Some((opcode, operands, span)),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match instruction {
+            Some((Opcode::If, operands, span)) => {
+                let condition = match operands.first() {
+                    Some(AstNode::Label { name, .. }) => {
+                        constants.get(name).is_some_and(|value| *value != 0)
+                    }
+                    _ => false,
+                };
+                stack.push(Frame {
+                    condition: active(&stack) && condition,
+                    seen_else: false,
+                    span: *span,
+                });
+            }
+            Some((Opcode::Else, _, span)) => match stack.last_mut() {
+                Some(frame) if !frame.seen_else => frame.seen_else = true,
+                Some(_) => {
+                    return Err(ErrorWithPosition::new(
+                        "Duplicate '.ELSE' in the same '.IF' block",
+                        span.start_pos(),
+                    ));
+                }
+                None => {
+                    return Err(ErrorWithPosition::new(
+                        "'.ELSE' without a matching '.IF'",
+                        span.start_pos(),
+                    ));
+                }
+            },
+            Some((Opcode::Endif, _, span)) => {
+                if stack.pop().is_none() {
+                    return Err(ErrorWithPosition::new(
+                        "'.ENDIF' without a matching '.IF'",
+                        span.start_pos(),
+                    ));
+                }
+            }
+            _ => {
+                if !active(&stack) {
+                    continue;
+                }
+                if let Some((Opcode::Equ, operands, _)) = instruction {
+                    if let (
+                        Some(AstNode::Label { name, .. }),
+                        Some(AstNode::ImmediateOperand(value)),
+                    ) = (operands.first(), operands.get(1))
+                    {
+                        constants.insert(name, *value);
+                    }
+                }
+                kept.push(line.clone());
+            }
+        }
+    }
+
+    if let Some(frame) = stack.last() {
+        return Err(ErrorWithPosition::new(
+            "'.IF' is missing its matching '.ENDIF'",
+            frame.span.start_pos(),
+        ));
+    }
+
+    Ok(AstNode::SectionScope {
+        origin: *origin,
+        content: kept,
+        span: *span,
+    })
+}
+
+/// Assembles LC-3 source text into an [`Assembly`], reporting the first
+/// error. Editor integrations that want everything at once should use
+/// [`assemble_all`].
+pub fn assemble(source: &str) -> Result<Assembly, ErrorWithPosition> {
+    assemble_with_options(source, &AssembleOptions::default())
+}
+
+/// [`assemble`] with explicit [`AssembleOptions`].
+pub fn assemble_with_options(
+    source: &str,
+    options: &AssembleOptions,
+) -> Result<Assembly, ErrorWithPosition> {
+    assemble_all_with_options(source, options).map_err(|errors| {
+        errors
+            .into_iter()
+            .next()
+            .expect("the error list is never empty")
+    })
+}
+
+/// Assembles LC-3 source text, collecting every positioned error across
+/// parsing and both emitter passes instead of stopping at the first.
+/// Offending lines are skipped with a best-effort size estimate so later
+/// errors still point at sensible addresses.
+pub fn assemble_all(source: &str) -> Result<Assembly, Vec<ErrorWithPosition>> {
+    assemble_all_with_options(source, &AssembleOptions::default())
+}
+
+/// [`assemble_all`] with explicit [`AssembleOptions`].
+pub fn assemble_all_with_options(
+    source: &str,
+    options: &AssembleOptions,
+) -> Result<Assembly, Vec<ErrorWithPosition>> {
+    let (ast, mut errors) = parse_collecting(source).map_err(|error| vec![error])?;
+    let mut assembly = Assembly::new();
+    assembly.case_insensitive_labels = options.case_insensitive_labels;
+    for node in &ast {
+        if matches!(node, AstNode::SectionScope { .. }) {
+            match apply_conditionals(node) {
+                Ok(node) => emit_section_collecting(&node, &mut assembly, options, &mut errors),
+                Err(error) => errors.push(error),
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(assembly)
+    } else {
+        errors.sort_by_key(|error| error.offset());
+        Err(errors)
+    }
+}
+
+/// A collection of assembler errors that renders every diagnostic together,
+/// one caret diagram per error.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    errors: Vec<ErrorWithPosition>,
+}
+
+impl Diagnostics {
+    pub fn errors(&self) -> &[ErrorWithPosition] {
+        &self.errors
+    }
+
+    /// The structured form of every collected error.
+    pub fn to_diagnostics(&self) -> Vec<Diagnostic> {
+        self.errors.iter().map(|error| error.to_diagnostic()).collect()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, error) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// [`assemble_all`] with the error list wrapped into a [`Diagnostics`]
+/// value that implements `Display` and `Error`.
+pub fn assemble_all_diagnostics(source: &str) -> Result<Assembly, Diagnostics> {
+    assemble_all(source).map_err(|errors| Diagnostics { errors })
+}
+
+/// Assembles `source`, reporting everything as structured [`Diagnostic`]
+/// values: warnings on success, errors on failure. The assembly is `None`
+/// exactly when at least one diagnostic is an error.
+pub fn assemble_with_diagnostics(source: &str) -> (Option<Assembly>, Vec<Diagnostic>) {
+    match assemble_all(source) {
+        Ok(assembly) => {
+            let warnings = assembly.warnings().to_vec();
+            (Some(assembly), warnings)
+        }
+        Err(errors) => (
+            None,
+            errors.iter().map(|error| error.to_diagnostic()).collect(),
+        ),
+    }
+}
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A line range (1-based, inclusive) of the expanded source that was spliced
+/// in from an included file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludedRange {
+    pub first_line: usize,
+    pub last_line: usize,
+    pub file: String,
+}
+
+/// Expands `.INCLUDE "path"` directives by splicing the resolved file
+/// contents into the source, before any parsing happens. Returns the
+/// expanded text together with the line ranges that came from included
+/// files, so errors can be attributed to them.
+pub fn expand_includes<R>(source: &str, resolver: &R) -> Result<(String, Vec<IncludedRange>), String>
+where
+    R: Fn(&str) -> Result<String, String>,
+{
+    let mut stack = Vec::new();
+    let mut ranges = Vec::new();
+    let expanded = expand_includes_inner(source, resolver, &mut stack, &mut ranges, 0)?;
+    Ok((expanded, ranges))
+}
+
+fn expand_includes_inner<R>(
+    source: &str,
+    resolver: &R,
+    stack: &mut Vec<String>,
+    ranges: &mut Vec<IncludedRange>,
+    line_offset: usize,
+) -> Result<String, String>
+where
+    R: Fn(&str) -> Result<String, String>,
+{
+    if stack.len() > MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "Include depth exceeds the limit of {} (via {})",
+            MAX_INCLUDE_DEPTH,
+            stack.join(" -> ")
+        ));
+    }
+
+    let mut output = String::new();
+    let mut line_number = line_offset;
+    for line in source.lines() {
+        let Some(path) = parse_include_line(line) else {
+            output.push_str(line);
+            output.push('\n');
+            line_number += 1;
+            continue;
+        };
+        if stack.iter().any(|included| **included == path) {
+            return Err(format!(
+                "Include cycle detected: {} -> {}",
+                stack.join(" -> "),
+                path
+            ));
+        }
+        let content = resolver(&path)
+            .map_err(|error| format!("Could not include \"{}\": {}", path, error))?;
+        stack.push(path.clone());
+        let expanded = expand_includes_inner(&content, resolver, stack, ranges, line_number)?;
+        stack.pop();
+        let included_lines = expanded.lines().count();
+        ranges.push(IncludedRange {
+            first_line: line_number + 1,
+            last_line: line_number + included_lines,
+            file: path,
+        });
+        output.push_str(&expanded);
+        line_number += included_lines;
+    }
+    Ok(output)
+}
+
+const MAX_MACRO_DEPTH: usize = 16;
+
+/// A line range (1-based, inclusive) of the expanded source that was
+/// produced by a macro invocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroRange {
+    pub first_line: usize,
+    pub last_line: usize,
+    pub name: String,
+    pub invoked_on_line: usize,
+}
+
+struct MacroDef {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+/// Expands `.MACRO name params` ... `.ENDM` definitions and their
+/// invocations, substituting parameters textually. Returns the expanded
+/// source together with the line ranges each invocation produced, so errors
+/// can be attributed to the invocation site.
+pub fn expand_macros(source: &str) -> Result<(String, Vec<MacroRange>), String> {
+    let mut macros: HashMap<String, MacroDef> = HashMap::new();
+    let lines: Vec<&str> = source.lines().collect();
+
+    // First sweep: collect definitions so invocations may precede them.
+    let mut index = 0;
+    while index < lines.len() {
+        let tokens: Vec<&str> = lines[index].split_whitespace().collect();
+        if tokens.first().is_some_and(|t| t.eq_ignore_ascii_case(".macro")) {
+            let name = tokens
+                .get(1)
+                .ok_or_else(|| format!(".MACRO on line {} is missing a name", index + 1))?
+                .to_uppercase();
+            let params = tokens[2..]
+                .iter()
+                .flat_map(|t| t.split(','))
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+                .collect();
+            let mut body = Vec::new();
+            loop {
+                index += 1;
+                let line = lines
+                    .get(index)
+                    .ok_or_else(|| format!("Macro '{}' is missing .ENDM", name))?;
+                let first = line.split_whitespace().next().unwrap_or("");
+                if first.eq_ignore_ascii_case(".endm") {
+                    break;
+                }
+                if first.eq_ignore_ascii_case(".macro") {
+                    return Err(format!(
+                        "Macro definitions cannot nest (line {}, inside macro '{}')",
+                        index + 1,
+                        name
+                    ));
+                }
+                body.push(line.to_string());
+            }
+            macros.insert(name, MacroDef { params, body });
+        }
+        index += 1;
+    }
+
+    // Second sweep: emit everything outside definitions, expanding
+    // invocations as we go.
+    let mut output = String::new();
+    let mut ranges = Vec::new();
+    let mut output_line = 0;
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        let first = line.split_whitespace().next().unwrap_or("");
+        if first.eq_ignore_ascii_case(".macro") {
+            while !lines[index]
+                .split_whitespace()
+                .next()
+                .unwrap_or("")
+                .eq_ignore_ascii_case(".endm")
+            {
+                index += 1;
+            }
+            index += 1;
+            continue;
+        }
+        if let Some(def) = macros.get(&first.to_uppercase()) {
+            let args: Vec<&str> = line
+                .split_whitespace()
+                .skip(1)
+                .flat_map(|t| t.split(','))
+                .filter(|t| !t.is_empty())
+                .collect();
+            if args.len() != def.params.len() {
+                return Err(format!(
+                    "Macro '{}' invoked on line {} expects {} argument(s), got {}",
+                    first.to_uppercase(),
+                    index + 1,
+                    def.params.len(),
+                    args.len()
+                ));
+            }
+            let expanded = expand_invocation(&macros, &first.to_uppercase(), &args, index + 1, 0)?;
+            ranges.push(MacroRange {
+                first_line: output_line + 1,
+                last_line: output_line + expanded.len(),
+                name: first.to_uppercase(),
+                invoked_on_line: index + 1,
+            });
+            for body_line in expanded {
+                output.push_str(&body_line);
+                output.push('\n');
+                output_line += 1;
+            }
+        } else {
+            output.push_str(line);
+            output.push('\n');
+            output_line += 1;
+        }
+        index += 1;
+    }
+    Ok((output, ranges))
+}
+
+fn expand_invocation(
+    macros: &HashMap<String, MacroDef>,
+    name: &str,
+    args: &[&str],
+    invoked_on_line: usize,
+    depth: usize,
+) -> Result<Vec<String>, String> {
+    if depth > MAX_MACRO_DEPTH {
+        return Err(format!(
+            "Macro expansion exceeds the depth limit of {} (in macro '{}' invoked on line {})",
+            MAX_MACRO_DEPTH, name, invoked_on_line
+        ));
+    }
+    let def = &macros[name];
+    let mut result = Vec::new();
+    for body_line in &def.body {
+        let mut line = body_line.clone();
+        for (param, arg) in def.params.iter().zip(args) {
+            line = substitute_word(&line, param, arg);
+        }
+        let first = line.split_whitespace().next().unwrap_or("").to_uppercase();
+        if let Some(inner) = macros.get(&first) {
+            let inner_args: Vec<&str> = line
+                .split_whitespace()
+                .skip(1)
+                .flat_map(|t| t.split(','))
+                .filter(|t| !t.is_empty())
+                .collect();
+            if inner_args.len() != inner.params.len() {
+                return Err(format!(
+                    "Macro '{}' invoked from macro '{}' (line {}) expects {} argument(s), got {}",
+                    first,
+                    name,
+                    invoked_on_line,
+                    inner.params.len(),
+                    inner_args.len()
+                ));
+            }
+            result.extend(expand_invocation(
+                macros,
+                &first,
+                &inner_args,
+                invoked_on_line,
+                depth + 1,
+            )?);
+        } else {
+            result.push(line);
+        }
+    }
+    Ok(result)
+}
+
+/// Replaces whole-word occurrences of `word` in `line`. Word boundaries are
+/// anything that is not alphanumeric or an underscore, matching the
+/// grammar's identifier rules.
+fn substitute_word(line: &str, word: &str, replacement: &str) -> String {
+    let is_word_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(offset) = rest.find(word) {
+        let before_ok = rest[..offset].chars().next_back().is_none_or(|c| !is_word_char(c));
+        let after_ok = rest[offset + word.len()..].chars().next().is_none_or(|c| !is_word_char(c));
+        result.push_str(&rest[..offset]);
+        if before_ok && after_ok {
+            result.push_str(replacement);
+        } else {
+            result.push_str(word);
+        }
+        rest = &rest[offset + word.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn parse_include_line(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    // Checked slicing: byte 8 can fall inside a multi-byte character on
+    // lines that are not include directives (comments allow any
+    // character), and that must not panic.
+    let rest = match trimmed.get(..8) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(".include") => trimmed[8..].trim(),
+        _ => return None,
+    };
+    let rest = rest.strip_prefix('"')?;
+    let end = rest.find('"')?;
+    Some(rest[..end].to_string())
+}
+
+/// Like [`assemble`], but expands `.INCLUDE` directives and `.MACRO`
+/// definitions first, resolving included file contents through `resolver`.
+/// This lets the CLI read includes from disk while the wasm build supplies
+/// them from memory. Errors inside a macro expansion are repositioned at
+/// the invocation line, since the expanded text never existed in a file
+/// the user could look at.
+pub fn assemble_with_resolver<R>(
+    source: &str,
+    resolver: R,
+) -> Result<Assembly, ErrorWithPosition>
+where
+    R: Fn(&str) -> Result<String, String>,
+{
+    let (include_expanded, include_ranges) = match expand_includes(source, &resolver) {
+        Ok(result) => result,
+        Err(message) => {
+            return Err(ErrorWithPosition::new(message, Position::from_start(source)));
+        }
+    };
+    let (expanded, macro_ranges) = match expand_macros(&include_expanded) {
+        Ok(result) => result,
+        Err(message) => {
+            return Err(ErrorWithPosition::new(message, Position::from_start(source)));
+        }
+    };
+    assemble(&expanded).map_err(|error| {
+        let line = error.line();
+        if let Some(range) = macro_ranges
+            .iter()
+            .find(|range| range.first_line <= line && line <= range.last_line)
+        {
+            let message = format!(
+                "{} (in expansion of macro '{}' invoked on line {})",
+                error.message(),
+                range.name,
+                range.invoked_on_line
+            );
+            let offset = line_start_offset(&include_expanded, range.invoked_on_line);
+            let position = Position::new(&include_expanded, offset)
+                .unwrap_or_else(|| Position::from_start(&include_expanded));
+            return ErrorWithPosition::new(message, position);
+        }
+        let range = include_ranges
+            .iter()
+            .find(|range| range.first_line <= line && line <= range.last_line);
+        match range {
+            Some(range) => {
+                let message =
+                    format!("{} (in file included as \"{}\")", error.message(), range.file);
+                error.with_message(message)
+            }
+            None => error,
+        }
+    })
+}
+
+/// The byte offset at which the given 1-based line starts.
+fn line_start_offset(source: &str, line: usize) -> usize {
+    source
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum()
+}
+
+/// Renders the higher-level [`AstNode`] tree as an indented outline, with
+/// resolved opcodes and operands. Friendlier than the derived `Debug`
+/// output when inspecting what the parser made of a program; for the raw
+/// pest pairs, see [`format_pair`].
+pub fn format_ast(nodes: &[AstNode<'_>]) -> String {
+    let mut output = String::new();
+    for node in nodes {
+        format_ast_node(node, 0, &mut output);
+    }
+    output
+}
+
+fn format_ast_node(node: &AstNode<'_>, indent_level: usize, output: &mut String) {
+    let indent = "  ".repeat(indent_level);
+    match node {
+        AstNode::SectionScope {
+            origin, content, ..
+        } => {
+            match origin {
+                Some(origin) => {
+                    output.push_str(&format!("{}SectionScope origin=x{:04X}\n", indent, origin))
+                }
+                None => output.push_str(&format!("{}SectionScope\n", indent)),
+            }
+            for line in content {
+                format_ast_node(line, indent_level + 1, output);
+            }
+        }
+        AstNode::Line { label, instruction } => {
+            output.push_str(&format!("{}Line\n", indent));
+            if let Some(label) = label {
+                format_ast_node(label, indent_level + 1, output);
+            }
+            if let Some(instruction) = instruction {
+                format_ast_node(instruction, indent_level + 1, output);
+            }
+        }
+        AstNode::Instruction {
+            opcode, operands, ..
+        } => {
+            // `mnemonic()` collapses all branch variants to "BR"; spell the
+            // condition flags out so the outline shows what was parsed.
+            let mnemonic = match opcode {
+                Opcode::Br { n, z, p } => format!(
+                    "BR{}{}{}",
+                    if *n { "n" } else { "" },
+                    if *z { "z" } else { "" },
+                    if *p { "p" } else { "" }
+                ),
+                _ => opcode.mnemonic(),
+            };
+            output.push_str(&format!("{}Instruction {}\n", indent, mnemonic));
+            for operand in operands {
+                format_ast_node(operand, indent_level + 1, output);
+            }
+        }
+        AstNode::PseudoInstruction { name, operands, .. } => {
+            output.push_str(&format!("{}PseudoInstruction {}\n", indent, name));
+            for operand in operands {
+                format_ast_node(operand, indent_level + 1, output);
+            }
+        }
+        AstNode::Label { name, .. } => {
+            output.push_str(&format!("{}Label {}\n", indent, name));
+        }
+        AstNode::AdjustedLabel { name, offset, .. } => {
+            output.push_str(&format!("{}AdjustedLabel {}{:+}\n", indent, name, offset));
+        }
+        AstNode::RegisterOperand(register) => {
+            output.push_str(&format!("{}Register {:?}\n", indent, register));
+        }
+        AstNode::ImmediateOperand(value) => {
+            output.push_str(&format!("{}Immediate x{:04X}\n", indent, value));
+        }
+        AstNode::StringLiteral(text) => {
+            output.push_str(&format!("{}String \"{}\"\n", indent, text));
+        }
+    }
+}
+
+/// Renders a pest pair (and its children) as an indented tree, which is
+/// invaluable when debugging grammar changes.
+pub fn format_pair<R: pest::RuleType>(pair: Pair<R>, indent_level: usize, is_newline: bool) -> String {
+    let indent = if is_newline {
+        "  ".repeat(indent_level)
+    } else {
+        String::new()
+    };
+
+    let children: Vec<_> = pair.clone().into_inner().collect();
+    let len = children.len();
+    let children: Vec<_> = children
+        .into_iter()
+        .map(|child| {
+            format_pair(
+                child,
+                if len > 1 {
+                    indent_level + 1
+                } else {
+                    indent_level
+                },
+                len > 1,
+            )
+        })
+        .collect();
+
+    let dash = if is_newline { "- " } else { "" };
+    match len {
+        0 => format!(
+            "{}{}{:?}: {:?}",
+            indent,
+            dash,
+            pair.as_rule(),
+            pair.as_str()
+        ),
+        1 => format!("{}{}{:?} > {}", indent, dash, pair.as_rule(), children[0]),
+        _ => format!(
+            "{}{}{:?}\n{}",
+            indent,
+            dash,
+            pair.as_rule(),
+            children.join("\n")
+        ),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub mod wasm {
+    use wasm_bindgen::prelude::*;
+
+    /// A successful assembly exposed to JavaScript. The label table and
+    /// source map come back as parallel arrays (index `i` of the name array
+    /// pairs with index `i` of the address array), since wasm-bindgen
+    /// cannot return maps without pulling in js-sys.
+    #[wasm_bindgen]
+    pub struct JsAssembly {
+        words: Vec<u16>,
+        origin: u16,
+        label_names: Vec<String>,
+        label_addresses: Vec<u16>,
+        source_map_addresses: Vec<u16>,
+        source_map_offsets: Vec<u32>,
+    }
+
+    #[wasm_bindgen]
+    impl JsAssembly {
+        /// The program words without the origin prefix; load them at
+        /// [`origin`](JsAssembly::origin).
+        pub fn words(&self) -> Vec<u16> {
+            self.words.clone()
+        }
+
+        pub fn origin(&self) -> u16 {
+            self.origin
+        }
+
+        #[wasm_bindgen(js_name = labelNames)]
+        pub fn label_names(&self) -> Vec<String> {
+            self.label_names.clone()
+        }
+
+        #[wasm_bindgen(js_name = labelAddresses)]
+        pub fn label_addresses(&self) -> Vec<u16> {
+            self.label_addresses.clone()
+        }
+
+        /// Addresses that have a known source location, paired with
+        /// [`sourceMapOffsets`](JsAssembly::source_map_offsets).
+        #[wasm_bindgen(js_name = sourceMapAddresses)]
+        pub fn source_map_addresses(&self) -> Vec<u16> {
+            self.source_map_addresses.clone()
+        }
+
+        /// Byte offsets into the source, one per source map address.
+        #[wasm_bindgen(js_name = sourceMapOffsets)]
+        pub fn source_map_offsets(&self) -> Vec<u32> {
+            self.source_map_offsets.clone()
+        }
+    }
+
+    /// A positioned assembler error exposed to JavaScript with 1-based
+    /// line and column fields, so the web UI can point at the source
+    /// instead of parsing a rendered string.
+    #[wasm_bindgen]
+    pub struct JsAssemblyError {
+        message: String,
+        line: u32,
+        column: u32,
+    }
+
+    #[wasm_bindgen]
+    impl JsAssemblyError {
+        #[wasm_bindgen(getter)]
+        pub fn message(&self) -> String {
+            self.message.clone()
+        }
+
+        #[wasm_bindgen(getter)]
+        pub fn line(&self) -> u32 {
+            self.line
+        }
+
+        #[wasm_bindgen(getter)]
+        pub fn column(&self) -> u32 {
+            self.column
+        }
+    }
+
+    /// Assembles LC-3 source into a [`JsAssembly`], or throws a
+    /// [`JsAssemblyError`] carrying the message and source position.
+    #[wasm_bindgen]
+    pub fn assemble_js(source: &str) -> Result<JsAssembly, JsAssemblyError> {
+        assemble_with_options_js(source, Some(crate::DEFAULT_ORIGIN), false, false)
+    }
+
+    /// [`assemble_js`] with the knobs of [`AssembleOptions`](crate::AssembleOptions)
+    /// spelled out: `default_origin` is used when the source lacks `.ORIG`
+    /// (pass `undefined` to reject such sources), `strict` treats warnings
+    /// as errors, and `case_insensitive_labels` folds label case.
+    #[wasm_bindgen]
+    pub fn assemble_with_options_js(
+        source: &str,
+        default_origin: Option<u16>,
+        strict: bool,
+        case_insensitive_labels: bool,
+    ) -> Result<JsAssembly, JsAssemblyError> {
+        // Struct update from the default so new option fields cannot
+        // break this cfg'd module again without native CI noticing.
+        let options = crate::AssembleOptions {
+            default_origin,
+            strict,
+            case_insensitive_labels,
+            ..Default::default()
+        };
+        let assembly =
+            crate::assemble_with_options(source, &options).map_err(|error| JsAssemblyError {
+                message: error.message().to_string(),
+                line: error.line() as u32,
+                column: error.column() as u32,
+            })?;
+        let mut labels: Vec<_> = assembly.labels().iter().collect();
+        labels.sort_by_key(|(_, location)| location.address);
+        let mut source_map: Vec<_> = assembly.source_map().iter().collect();
+        source_map.sort_by_key(|(address, _)| **address);
+        Ok(JsAssembly {
+            words: assembly.words().to_vec(),
+            origin: assembly.origin(),
+            label_names: labels.iter().map(|(name, _)| (*name).clone()).collect(),
+            label_addresses: labels
+                .iter()
+                .map(|(_, location)| location.address)
+                .collect(),
+            source_map_addresses: source_map.iter().map(|(address, _)| **address).collect(),
+            source_map_offsets: source_map
+                .iter()
+                .map(|(_, offset)| **offset as u32)
+                .collect(),
+        })
+    }
+
+    /// Like [`assemble_js`], but returns a JSON string of the form
+    /// `{"words": [...], "symbols": {"LOOP": 12288}}` so the playground
+    /// can show where labels landed. Hand-built JSON keeps the crate free
+    /// of serialization dependencies; label names are plain identifiers,
+    /// so no escaping is needed.
+    #[wasm_bindgen]
+    pub fn assemble_with_symbols_js(source: &str) -> Result<JsValue, JsValue> {
+        let assembly =
+            crate::assemble(source).map_err(|error| JsValue::from_str(&error.to_string()))?;
+        let words: Vec<String> = assembly
+            .data()
+            .iter()
+            .map(|word| word.to_string())
+            .collect();
+        let mut labels: Vec<_> = assembly.labels().iter().collect();
+        labels.sort_by_key(|(_, location)| location.address);
+        let symbols: Vec<String> = labels
+            .into_iter()
+            .map(|(name, location)| format!("\"{}\": {}", name, location.address))
+            .collect();
+        Ok(JsValue::from_str(&format!(
+            "{{\"words\": [{}], \"symbols\": {{{}}}}}",
+            words.join(", "),
+            symbols.join(", ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADD_IMMEDIATE: &str = include_str!("../../testcases/add_immediate.asm");
+    const BR: &str = include_str!("../../testcases/br.asm");
+    const PUTS: &str = include_str!("../../testcases/puts.asm");
+
+    #[test]
+    fn test_lex_file() {
+        parse(ADD_IMMEDIATE).unwrap();
+        parse(BR).unwrap();
+        parse(PUTS).unwrap();
+    }
+
+    #[test]
+    fn test_labels_may_not_shadow_opcode_names() {
+        let error = parse(".ORIG x3000\nHALT .FILL x0\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Label 'HALT' collides with the opcode of the same name"
+        );
+
+        let error = parse(".ORIG x3000\nadd .FILL x0\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Label 'add' collides with the opcode of the same name"
+        );
+
+        // Names that merely start with a mnemonic stay legal.
+        let assembly = assemble(".ORIG x3000\nADDER .FILL x1\nBREAK BRnzp BREAK\n.END\n").unwrap();
+        assert_eq!(assembly.address_of("ADDER"), Some(0x3000));
+        assert_eq!(assembly.address_of("BREAK"), Some(0x3001));
+    }
+
+    #[test]
+    fn test_content_outside_a_section_is_a_specific_error() {
+        let error = assemble(".ORIG x3000\nHALT\n.END\nADD R0, R0, #1\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Instruction found outside of a '.ORIG'/'.END' section"
+        );
+        assert_eq!(error.line(), 4);
+
+        let error = assemble("ADD R0, R0, #1\n.ORIG x3000\nHALT\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Instruction found outside of a '.ORIG'/'.END' section"
+        );
+        assert_eq!(error.line(), 1);
+
+        // Comments outside sections stay legal.
+        assemble("; prologue\n.ORIG x3000\nHALT\n.END\n; epilogue\n").unwrap();
+    }
+
+    #[test]
+    fn test_missing_trailing_newline_is_accepted() {
+        // `.END` at EOF.
+        let assembly = assemble(".ORIG x3000\nADD R0, R0, #1\n.END").unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x1021]);
+
+        // A comment at EOF, both on the `.END` line and on its own.
+        assemble(".ORIG x3000\nHALT\n.END ; done").unwrap();
+        assemble(".ORIG x3000\nHALT\n.END\n; trailing note").unwrap();
+
+        // A genuinely malformed last line still gets a positioned error.
+        let error = assemble(".ORIG x3000\nADD R0, @").unwrap_err();
+        assert_eq!(error.line(), 2);
+    }
+
+    #[test]
+    fn test_crlf_and_tab_variants_assemble_identically() {
+        let reference = assemble(BR).unwrap();
+
+        let crlf = BR.replace('\n', "\r\n");
+        let assembly = assemble(&crlf).unwrap();
+        assert_eq!(assembly.data(), reference.data());
+
+        let tabbed = BR.replace(' ', "\t");
+        let assembly = assemble(&tabbed).unwrap();
+        assert_eq!(assembly.data(), reference.data());
+    }
+
+    #[test]
+    fn test_unknown_opcodes_get_a_suggestion() {
+        let error = parse(".ORIG x3000\nADDD R0, R0, #1\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Unknown opcode 'addd', did you mean 'ADD'?"
+        );
+
+        // Dot-directives are deferred to the emitter, where the pseudo-op
+        // registry gets a say before they count as unknown.
+        let error = assemble(".ORIG x3000\n.FILLL #1\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Unknown opcode '.filll', did you mean '.FILL'?"
+        );
+    }
+
+    #[test]
+    fn test_align_columns_pads_to_the_widest_cell() {
+        let rows = vec![
+            vec!["x3000", "evaluated", "5"],
+            vec!["x300A", "taken", "12345"],
+        ];
+        assert_eq!(
+            output::align_columns(&rows),
+            vec!["x3000  evaluated  5", "x300A  taken      12345"]
+        );
+    }
+
+    #[test]
+    fn test_no_color_silences_auto_but_not_an_explicit_always() {
+        use output::ColorChoice;
+        assert!(ColorChoice::Auto.enabled_with(false, true));
+        assert!(!ColorChoice::Auto.enabled_with(true, true));
+        assert!(!ColorChoice::Auto.enabled_with(false, false));
+        assert!(ColorChoice::Always.enabled_with(true, false));
+        assert!(!ColorChoice::Never.enabled_with(false, true));
+    }
+
+    #[test]
+    fn test_disassembly_round_trips_the_testcase_programs() {
+        for source in [ADD_IMMEDIATE, BR, PUTS] {
+            let assembly = assemble(source).unwrap();
+            let data = assembly.data();
+            let mut text = disassembler::disassemble(&data[1..], data[0]).join("\n");
+            text.push('\n');
+            let reassembled = assemble(&text).unwrap();
+            assert_eq!(reassembled.data(), data, "round-trip failed for:\n{}", text);
+        }
+    }
+
+    #[test]
+    fn test_disassembly_labels_branch_targets() {
+        let assembly = assemble(BR).unwrap();
+        let data = assembly.data();
+        let lines = disassembler::disassemble(&data[1..], data[0]);
+        assert!(lines.contains(&"L_3002 ADD R0, R0, #-1".to_string()));
+        assert!(lines.contains(&"BRp L_3002".to_string()));
+    }
+
+    #[test]
+    fn test_words_excludes_the_origin_header() {
+        let assembly = assemble(".ORIG x3000\nADD R0, R0, #1\n.END\n").unwrap();
+        assert_eq!(assembly.origin(), 0x3000);
+        assert_eq!(assembly.words(), &[0x1021]);
+        assert_eq!(assembly.data(), &[0x3000, 0x1021]);
+    }
+
+    #[test]
+    fn test_labels_are_exposed_with_their_addresses() {
+        let assembly =
+            assemble(".ORIG x3000\nLOOP ADD R0, R0, #1\nDATA .FILL #7\n.END\n").unwrap();
+        assert_eq!(assembly.address_of("LOOP"), Some(0x3000));
+        assert_eq!(assembly.address_of("DATA"), Some(0x3001));
+        assert_eq!(assembly.address_of("MISSING"), None);
+        assert_eq!(assembly.labels().len(), 2);
+    }
+
+    #[test]
+    fn test_listing_shows_addresses_words_and_source() {
+        let source = ".ORIG x3000\nADD R0, R0, #1\nMSG .STRINGZ \"Hi\"\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(
+            assembly.to_listing(source),
+            "x3000  x1021  ADD R0, R0, #1\n\
+             x3001  x0048  MSG .STRINGZ \"Hi\"\n\
+             x3002  x0069  ..\n\
+             x3003  x0000  ..\n"
+        );
+    }
+
+    #[test]
+    fn test_symbol_file_matches_the_lc3tools_format() {
+        let assembly = assemble(
+            ".ORIG x3000\nSTART ADD R0, R0, #1\nLOOP BRnzp LOOP\nDATA .FILL #0\n.END\n",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        assembly.write_symbol_file(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "// Symbol table\n\
+             // Scope level 0:\n\
+             //\tSymbol Name       Page Address\n\
+             //\t----------------  ------------\n\
+             //\tSTART             3000\n\
+             //\tLOOP              3001\n\
+             //\tDATA              3002\n"
+        );
+    }
+
+    #[test]
+    fn test_unknown_labels_get_a_suggestion() {
+        let error =
+            assemble(".ORIG x3000\nLOOP ADD R0, R0, #1\nBRnzp LOPP\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "Label 'LOPP' was never defined, did you mean 'LOOP'?"
+        );
+
+        // Nothing plausibly close: no suggestion.
+        let error = assemble(".ORIG x3000\nLOOP ADD R0, R0, #1\nBRnzp ELSEWHERE\n.END\n")
+            .unwrap_err();
+        assert_eq!(error.message(), "Label 'ELSEWHERE' was never defined");
+    }
+
+    #[test]
+    fn test_gibberish_opcodes_get_no_suggestion() {
+        let error = Opcode::from("xqzzyw").unwrap_err();
+        assert_eq!(error, "Unknown opcode 'xqzzyw'");
+    }
+
+    #[test]
+    fn test_mistyped_operands_are_rejected_at_parse_time() {
+        let error = parse(".ORIG x3000\nADD #1, #1\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "'ADD' expects a register as first operand, got immediate '#1'"
+        );
+    }
+
+    #[test]
+    fn test_wrong_arity_errors_quote_the_expected_signature() {
+        let error = parse(".ORIG x3000\nADD R0, R0\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "'ADD' expects 3 operands, got 2 \
+             (usage: ADD <register>, <register>, <register|immediate>)"
+        );
+
+        let error = parse(".ORIG x3000\nNOT R0, R1, R2\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "'NOT' expects 2 operands, got 3 (usage: NOT <register>, <register>)"
+        );
+
+        let error = parse(".ORIG x3000\n.BLKW\n.END\n").unwrap_err();
+        assert_eq!(
+            error.message(),
+            "'.BLKW' expects 1 to 2 operands, got 0 \
+             (usage: .BLKW <label|immediate>, [<label|immediate>])"
+        );
+    }
+
+    #[test]
+    fn test_operand_signatures_cover_every_opcode() {
+        // One representative mistake per opcode; the fragment must appear
+        // in the resulting error message.
+        let cases: &[(&str, &str)] = &[
+            ("ADD R0, R0", "'ADD' expects 3 operands, got 2"),
+            ("AND R0, #1, R2", "'AND' expects a register as second operand"),
+            ("BR R0", "'BR' expects a label or immediate as first operand"),
+            ("JMP #4", "'JMP' expects a register as first operand"),
+            ("JSR R2", "'JSR' expects a label or immediate as first operand"),
+            ("JSRR LOOP", "'JSRR' expects a register as first operand"),
+            ("LD R0", "'LD' expects 2 operands, got 1"),
+            ("LDI #1, LOOP", "'LDI' expects a register as first operand"),
+            ("LDR R0, R1", "'LDR' expects 3 operands, got 2"),
+            ("LEA R0, R1", "'LEA' expects a label or immediate as second operand"),
+            ("NOT R0", "'NOT' expects 2 operands, got 1"),
+            ("RET R6", "'RET' does not take operands, got 1"),
+            ("RTI R0", "'RTI' does not take operands, got 1"),
+            ("ST LOOP, R0", "'ST' expects a register as first operand"),
+            ("STI R0, LOOP, R2", "'STI' expects 2 operands, got 3"),
+            ("STR R0, #1, #2", "'STR' expects a register as second operand"),
+            ("TRAP R0", "'TRAP' expects a label or immediate as first operand"),
+            ("GETC R0", "'GETC' does not take operands, got 1"),
+            ("OUT R0", "'OUT' does not take operands, got 1"),
+            ("PUTS R0", "'PUTS' does not take operands, got 1"),
+            ("IN R0", "'IN' does not take operands, got 1"),
+            ("PUTSP R0", "'PUTSP' does not take operands, got 1"),
+            ("HALT R0", "'HALT' does not take operands, got 1"),
+            ("NOP R0", "'NOP' does not take operands, got 1"),
+            (".FILL R0", "'.FILL' expects a label or immediate as first operand"),
+            (".BLKW #1, #0, #0", "'.BLKW' expects 1 to 2 operands, got 3"),
+            (".STRINGZ", "'.STRINGZ' expects at least 1 operand, got 0"),
+            (".STRINGZ #1", "'.STRINGZ' expects a string as first operand"),
+            (".STRINGZP R0", "'.STRINGZP' expects a string as first operand"),
+            (".ASSERT #1, #2", "'.ASSERT' expects a register or label as first operand"),
+            (".EQU #1, #2", "'.EQU' expects a label as first operand"),
+            (".IF", "'.IF' expects 1 operand, got 0"),
+            (".ELSE R0", "'.ELSE' does not take operands, got 1"),
+            (".ENDIF R0", "'.ENDIF' does not take operands, got 1"),
+        ];
+        for (line, fragment) in cases {
+            let source = format!(".ORIG x3000\n{}\n.END\n", line);
+            let error = parse(&source).unwrap_err();
+            assert!(
+                error.message().contains(fragment),
+                "for '{}', expected '{}' in message: {}",
+                line,
+                fragment,
+                error.message()
+            );
+        }
+    }
+
+    #[test]
+    fn test_assemble_add_immediate() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x1025, 0x102A, 0xF025]);
+    }
+
+    #[test]
+    fn test_assemble_br() {
+        let assembly = assemble(BR).unwrap();
+        assert_eq!(
+            assembly.data(),
+            &[0x3000, 0x5020, 0x1025, 0x103F, 0x03FE, 0xF025]
+        );
+    }
+
+    #[test]
+    fn test_equ_constant_usable_as_immediate() {
+        let source = ".ORIG x3000\n.EQU COUNT #5\nADD R0, R0, COUNT\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x1025]);
+    }
+
+    #[test]
+    fn test_equ_occupies_no_memory() {
+        let with_equ = ".ORIG x3000\nADD R0, R0, #1\n.EQU FOO x10\nLOOP ADD R0, R0, #1\nBRp LOOP\n.END\n";
+        let without_equ = ".ORIG x3000\nADD R0, R0, #1\nLOOP ADD R0, R0, #1\nBRp LOOP\n.END\n";
+        assert_eq!(
+            assemble(with_equ).unwrap().data(),
+            assemble(without_equ).unwrap().data()
+        );
+    }
+
+    #[test]
+    fn test_equ_redefinition_is_an_error() {
+        let source = ".ORIG x3000\n.EQU FOO #1\n.EQU FOO #2\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("already defined at line 2"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_undefined_constant_is_reported_as_such() {
+        let source = ".ORIG x3000\nADD R0, R0, NOPE\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("Undefined constant 'NOPE'"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_expressions_fold_to_immediate_operands() {
+        let source = ".ORIG x3000\nADD R0, R0, #(1<<3)\n.FILL #2*4+1\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x1028, 0x0009]);
+    }
+
+    #[test]
+    fn test_expressions_use_c_like_precedence() {
+        let source = ".ORIG x3000\n.FILL #2+3*4\n.FILL #(2+3)*4\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 14, 20]);
+    }
+
+    #[test]
+    fn test_expressions_can_reference_earlier_equ_constants() {
+        let source = ".ORIG x3000\n.EQU BASE x10\n.FILL #BASE*2+1\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x0021]);
+    }
+
+    #[test]
+    fn test_expression_division_by_zero_is_an_error() {
+        let source = ".ORIG x3000\n.FILL #1/0\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("Division by zero"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_expression_overflowing_16_bits_is_an_error() {
+        let source = ".ORIG x3000\n.FILL #(1<<15)*4\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("does not fit into 16 bits"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    /// Re-parses Intel HEX text back into (byte address, data bytes) pairs,
+    /// verifying each record's checksum.
+    fn parse_intel_hex(text: &str) -> Vec<(u32, Vec<u8>)> {
+        let mut records = Vec::new();
+        for line in text.lines() {
+            let line = line.strip_prefix(':').expect("records start with ':'");
+            let bytes: Vec<u8> = (0..line.len() / 2)
+                .map(|i| u8::from_str_radix(&line[i * 2..i * 2 + 2], 16).unwrap())
+                .collect();
+            let sum: u32 = bytes.iter().map(|b| *b as u32).sum();
+            assert_eq!(sum % 256, 0, "checksum mismatch in record ':{}'", line);
+            let length = bytes[0] as usize;
+            let address = (bytes[1] as u32) << 8 | bytes[2] as u32;
+            let record_type = bytes[3];
+            match record_type {
+                0 => records.push((address, bytes[4..4 + length].to_vec())),
+                1 => break,
+                other => panic!("unexpected record type {}", other),
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn test_intel_hex_round_trip() {
+        let assembly = assemble(BR).unwrap();
+        let hex = assembly.to_intel_hex();
+
+        let mut words = Vec::new();
+        let mut next_address = None;
+        for (address, bytes) in parse_intel_hex(&hex) {
+            if let Some(expected) = next_address {
+                assert_eq!(address, expected, "records are not contiguous");
+            }
+            next_address = Some(address + bytes.len() as u32);
+            for pair in bytes.chunks(2) {
+                words.push((pair[0] as u16) << 8 | pair[1] as u16);
+            }
+        }
+
+        assert_eq!(words, assembly.data()[1..]);
+        let first_address = parse_intel_hex(&hex)[0].0;
+        assert_eq!(first_address, assembly.data()[0] as u32 * 2);
+    }
+
+    #[test]
+    fn test_intel_hex_ends_with_eof_record() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        assert!(assembly.to_intel_hex().ends_with(":00000001FF\n"));
+    }
+
+    #[test]
+    fn test_from_ihex_round_trips_the_assembly() {
+        let assembly = assemble(BR).unwrap();
+        let segments = formats::from_ihex(&assembly.to_intel_hex()).unwrap();
+        assert_eq!(segments, vec![(0x3000, assembly.words().to_vec())]);
+    }
+
+    #[test]
+    fn test_ihex_round_trips_high_origins_with_extended_addresses() {
+        // x8000 doubles to byte address x10000, past what a record's
+        // 16-bit address field can hold; the output must carry a type-04
+        // extended linear address record instead of truncating.
+        let assembly = assemble(".ORIG x8000\nADD R0, R0, #1\nHALT\n.END\n").unwrap();
+        let text = assembly.to_intel_hex();
+        assert!(
+            text.contains(":020000040001F9"),
+            "no extended linear address record in:\n{}",
+            text
+        );
+        let segments = formats::from_ihex(&text).unwrap();
+        assert_eq!(segments, vec![(0x8000, assembly.words().to_vec())]);
+    }
+
+    #[test]
+    fn test_ihex_records_do_not_straddle_the_64k_boundary() {
+        // Byte address xFFFA plus six words crosses x10000 mid-program;
+        // the records must split there and still merge back into one
+        // contiguous segment on the way in.
+        let source = ".ORIG x7FFD\n.FILL x1\n.FILL x2\n.FILL x3\n.FILL x4\n.FILL x5\n.FILL x6\n.END\n";
+        let assembly = assemble(source).unwrap();
+        let segments = formats::from_ihex(&assembly.to_intel_hex()).unwrap();
+        assert_eq!(segments, vec![(0x7FFD, vec![1, 2, 3, 4, 5, 6])]);
+    }
+
+    #[test]
+    fn test_from_ihex_rejects_a_bad_checksum() {
+        let error = formats::from_ihex(":02600000123456\n:00000001FF\n").unwrap_err();
+        assert!(
+            error.contains("line 1: checksum mismatch"),
+            "unexpected message: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_from_ihex_requires_the_eof_record() {
+        let error = formats::from_ihex(":02600000123458\n").unwrap_err();
+        assert!(
+            error.contains("missing end-of-file record"),
+            "unexpected message: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_memory_image_places_the_words_at_the_origin() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        let (image, origin) = assembly.into_memory_image();
+        assert_eq!(origin, 0x3000);
+        assert_eq!(&image[0x3000..0x3003], assembly.words());
+        assert_eq!(image[0x2FFF], 0);
+        assert_eq!(image[0x3003], 0);
+    }
+
+    #[test]
+    fn test_line_map_resolves_addresses_to_lines() {
+        let source = ".ORIG x3000\nADD R0, R0, #1\nLOOP ADD R0, R0, #-1\nBRp LOOP\nHALT\n.END\n";
+        let assembly = assemble(source).unwrap();
+        let lines = assembly.line_map(source);
+        assert_eq!(lines[&0x3000], 2);
+        assert_eq!(lines[&0x3001], 3);
+        assert_eq!(lines[&0x3002], 4);
+        assert_eq!(lines[&0x3003], 5);
+    }
+
+    #[test]
+    fn test_source_lines_resolve_to_line_and_column() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        let lines = assembly.source_lines(ADD_IMMEDIATE);
+        // The comment and `.ORIG` occupy lines 1 and 2; the instructions
+        // start on line 3, one word each.
+        assert_eq!(lines[&0x3000], (3, 1));
+        assert_eq!(lines[&0x3001], (4, 1));
+        assert_eq!(lines[&0x3002], (5, 1));
+    }
+
+    #[test]
+    fn test_hex_dump_leads_with_the_origin() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        assert_eq!(assembly.to_hex_dump(), "3000\n1025\n102A\nF025\n");
+    }
+
+    #[test]
+    fn test_bin_text_leads_with_the_origin() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        assert_eq!(
+            assembly.to_bin_text(),
+            "0011000000000000\n\
+             0001000000100101\n\
+             0001000000101010\n\
+             1111000000100101\n"
+        );
+    }
+
+    #[test]
+    fn test_c_array_holds_the_words_with_the_origin_in_a_comment() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        assert_eq!(
+            assembly.to_c_array(),
+            "/* .ORIG x3000 */\n\
+             const unsigned short program[3] = {\n\
+             \x20   0x1025, 0x102A, 0xF025,\n\
+             };\n"
+        );
+    }
+
+    #[test]
+    fn test_overrunning_addressable_memory_is_an_error() {
+        let source = ".ORIG xA000\n.BLKW x7000\nADD R0, R0, #1\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("overruns addressable memory"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_a_program_may_end_exactly_at_the_last_address() {
+        // x9000 + x7000 words lands exactly on the x10000 boundary.
+        let source = ".ORIG x9000\n.BLKW x7000\n.END\n";
+        assert!(assemble(source).is_ok());
+    }
+
+    #[test]
+    fn test_missing_orig_defaults_to_x3000_with_a_warning() {
+        let source = "ADD R0, R0, #1\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x1021]);
+        assert_eq!(assembly.warnings().len(), 1);
+        let warning = &assembly.warnings()[0];
+        assert_eq!(warning.severity, Severity::Warning);
+        assert!(
+            warning.message.contains("defaulting to x3000"),
+            "unexpected message: {}",
+            warning.message
+        );
+    }
+
+    #[test]
+    fn test_missing_orig_is_an_error_when_the_default_is_disabled() {
+        let source = "ADD R0, R0, #1\n.END\n";
+        let options = AssembleOptions {
+            default_origin: None,
+            ..Default::default()
+        };
+        let error = assemble_with_options(source, &options).unwrap_err();
+        assert!(
+            error.message().contains("Missing '.ORIG'"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_default_origin_override_relocates_a_snippet() {
+        let source = "ADD R0, R0, #1\n.END\n";
+        let options = AssembleOptions {
+            default_origin: Some(0x4000),
+            ..Default::default()
+        };
+        let assembly = assemble_with_options(source, &options).unwrap();
+        assert_eq!(assembly.data(), &[0x4000, 0x1021]);
+        assert!(
+            assembly.warnings()[0].message.contains("defaulting to x4000"),
+            "unexpected message: {}",
+            assembly.warnings()[0].message
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_turns_the_missing_orig_warning_into_an_error() {
+        let source = "ADD R0, R0, #1\n.END\n";
+        let options = AssembleOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let error = assemble_with_options(source, &options).unwrap_err();
+        assert!(
+            error.message().contains("No '.ORIG' directive"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_labels_match_across_cases() {
+        let source = ".ORIG x3000\nloop ADD R0, R0, #1\nBRp LOOP\n.END\n";
+        assert!(assemble(source).is_err());
+
+        let options = AssembleOptions {
+            case_insensitive_labels: true,
+            ..Default::default()
+        };
+        let assembly = assemble_with_options(source, &options).unwrap();
+        assert_eq!(assembly.data(), &[0x3000, 0x1021, 0x03FE]);
+        assert_eq!(assembly.address_of("LOOP"), Some(0x3000));
+    }
+
+    #[test]
+    fn test_an_explicit_orig_produces_no_warning() {
+        let assembly = assemble(ADD_IMMEDIATE).unwrap();
+        assert!(assembly.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_errors_outlive_the_source_they_came_from() {
+        let error = {
+            let source = String::from(".ORIG x3000\nBRp NOWHERE\n.END\n");
+            assemble(&source).unwrap_err()
+        };
+        // The source is gone; the caret diagram must render regardless.
+        let rendered = error.to_string();
+        assert!(rendered.contains("--> 2:1"), "bad rendering: {}", rendered);
+        assert!(
+            rendered.contains("2 | BRp NOWHERE"),
+            "bad rendering: {}",
+            rendered
+        );
+        assert!(rendered.contains("^---"), "bad rendering: {}", rendered);
+    }
+
+    #[test]
+    fn test_undefined_label_is_an_error() {
+        let source = ".ORIG x3000\nBRp NOWHERE\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("Label 'NOWHERE' was never defined"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_immediate_normalization() {
+        // Decimal immediates must fit into an i16.
+        let error = assemble(".ORIG x3000\n.FILL #-40000\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("[-32768, 32767]"),
+            "unexpected message: {}",
+            error.message()
+        );
+        // Hex immediates must fit into a u16.
+        let error = assemble(".ORIG x3000\n.FILL xFFFF1\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("[x0, xFFFF]"),
+            "unexpected message: {}",
+            error.message()
+        );
+        // Negative fills stay legal and emit their two's-complement word.
+        let assembly = assemble(".ORIG x3000\n.FILL #-1\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0xFFFF]);
+    }
+
+    #[test]
+    fn test_imm5_boundaries() {
+        assert!(assemble(".ORIG x3000\nADD R0, R0, #16\n.END\n").is_err());
+        assert!(assemble(".ORIG x3000\nADD R0, R0, #-17\n.END\n").is_err());
+        let assembly = assemble(".ORIG x3000\nADD R0, R0, #15\nADD R0, R0, #-16\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0x102F, 0x1030]);
+    }
+
+    #[test]
+    fn test_offset6_boundaries() {
+        let assembly =
+            assemble(".ORIG x3000\nLDR R0, R1, #31\nSTR R0, R1, #-32\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0x605F, 0x7060]);
+        assert!(assemble(".ORIG x3000\nLDR R0, R1, #32\n.END\n").is_err());
+        let error = assemble(".ORIG x3000\nSTR R0, R1, #-33\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("Str") && error.message().contains("-32 to 31"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_imm5_overflow_is_an_error() {
+        let error = assemble(".ORIG x3000\nADD R0, R0, #50\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("Add") && error.message().contains("valid range is -16 to 15"),
+            "unexpected message: {}",
+            error.message()
+        );
+        assert!(
+            error.message().contains("50"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_too_distant_ld_label_is_an_error() {
+        let source = ".ORIG x3000\nLD R0, FAR\nTRAP x25\n.BLKW #300\nFAR .FILL #1\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("Label 'FAR' is 301 instructions away")
+                && error.message().contains("-256 to 255"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_offset_just_over_the_9_bit_limit_is_an_error() {
+        // FAR sits exactly 256 instructions past PC+1; 255 is the limit.
+        let source = ".ORIG x3000\nLD R0, FAR\n.BLKW #256\nFAR .FILL #1\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("Label 'FAR' is 256 instructions away")
+                && error.message().contains("9-bit offset")
+                && error.message().contains("-256 to 255"),
+            "unexpected message: {}",
+            error.message()
+        );
+
+        // One word closer assembles fine.
+        let source = ".ORIG x3000\nLD R0, FAR\n.BLKW #255\nFAR .FILL #1\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data()[1], 0x20FF);
+    }
+
+    #[test]
+    fn test_label_arithmetic_adjusts_the_target() {
+        // LD R0, TABLE+1 skips the table header word.
+        let source = ".ORIG x3000\nLD R0, TABLE+1\nTRAP x25\nTABLE .FILL #2\n.FILL #42\n.END\n";
+        let assembly = assemble(source).unwrap();
+        // TABLE is at x3002, so TABLE+1 is offset 2 from PC+1 (x3001).
+        assert_eq!(assembly.data()[1], 0x2002);
+
+        let minus = assemble(".ORIG x3000\nBRnzp HERE-1\nHERE TRAP x25\n.END\n").unwrap();
+        // HERE-1 is the branch itself: offset -1 from PC+1.
+        assert_eq!(minus.data()[1], 0x0FFF);
+    }
+
+    #[test]
+    fn test_assemble_all_collects_every_error() {
+        let source = ".ORIG x3000\nLD R0, MISSING\nADD R0, R0, #99\nTRAP x25\n.END\n";
+        let errors = assemble_all(source).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].message().contains("Label 'MISSING' was never defined"));
+        assert!(errors[1].message().contains("does not fit into 5 bits"));
+
+        // `assemble` keeps its first-error contract.
+        let first = assemble(source).unwrap_err();
+        assert_eq!(first.message(), errors[0].message());
+    }
+
+    #[test]
+    fn test_structured_diagnostics_carry_line_and_column() {
+        let source = ".ORIG x3000\nADD R0, R0, #99\nTRAP x25\n.END\n";
+        let (assembly, diagnostics) = assemble_with_diagnostics(source);
+        assert!(assembly.is_none());
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 1));
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.code, "value-out-of-range");
+        assert!(diagnostic.message.contains("does not fit into 5 bits"));
+    }
+
+    #[test]
+    fn test_diagnostics_include_warnings_on_success() {
+        let (assembly, diagnostics) = assemble_with_diagnostics("ADD R0, R0, #1\n.END\n");
+        assert!(assembly.is_some());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].code, "missing-orig");
+    }
+
+    #[test]
+    fn test_diagnostics_render_as_json() {
+        let (_, diagnostics) = assemble_with_diagnostics(".ORIG x3000\nBRp \"x\"\n.END\n");
+        let json = diagnostics_to_json(&diagnostics);
+        assert!(json.starts_with("[{\"severity\": \"error\""), "got: {}", json);
+        assert!(json.contains("\"line\": 2"), "got: {}", json);
+        // The message quotes the string operand; the quotes must survive
+        // as valid JSON.
+        assert!(json.contains("\\\"x\\\""), "got: {}", json);
+    }
+
+    #[test]
+    fn test_diagnostics_report_errors_from_every_stage() {
+        // An operand-validation error, an undefined label and an immediate
+        // range error, on three different lines.
+        let source =
+            ".ORIG x3000\nADD #1, R0, R0\nLD R0, MISSING\nADD R0, R0, #99\nTRAP x25\n.END\n";
+        let diagnostics = assemble_all_diagnostics(source).unwrap_err();
+        let lines: Vec<usize> = diagnostics
+            .errors()
+            .iter()
+            .map(|error| error.line())
+            .collect();
+        assert_eq!(lines, vec![2, 3, 4]);
+        // The combined rendering mentions each problem.
+        let rendered = diagnostics.to_string();
+        assert!(rendered.contains("expects a register"));
+        assert!(rendered.contains("MISSING"));
+        assert!(rendered.contains("does not fit"));
+    }
+
+    #[test]
+    fn test_duplicate_label_error_shows_both_definition_sites() {
+        let source = ".ORIG x3000\nLOOP ADD R0, R0, #1\nTRAP x25\nLOOP .FILL #0\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert_eq!(error.message(), "Label 'LOOP' is already defined at line 2");
+        // The rendered error points at the redefinition on line 4.
+        assert!(
+            error.to_string().contains("4"),
+            "unexpected rendering: {}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_fill_stores_the_absolute_address_of_a_label() {
+        // PTR comes before MY_DATA, so this exercises forward resolution
+        // in the second pass.
+        let source = ".ORIG x3000\nPTR .FILL MY_DATA\nMY_DATA .FILL #42\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data()[1], 0x3001);
+        assert_eq!(assembly.data()[2], 42);
+    }
+
+    #[test]
+    fn test_fill_with_an_undefined_label_is_an_error() {
+        let error = assemble(".ORIG x3000\n.FILL NOWHERE\n.END\n").unwrap_err();
+        assert!(error.message.contains("Label 'NOWHERE' was never defined"));
+    }
+
+    #[test]
+    fn test_label_arithmetic_accepts_hex_adjustments() {
+        let source = ".ORIG x3000\nLD R0, TABLE+x2\nTRAP x25\nTABLE .FILL #0\n.FILL #0\n.FILL #7\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data()[1], 0x2003);
+    }
+
+    #[test]
+    fn test_blkw_fills_with_an_explicit_value() {
+        let assembly = assemble(".ORIG x3000\n.BLKW #4, xFFFF\n.END\n").unwrap();
+        assert_eq!(&assembly.data()[1..], &[0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF]);
+
+        // Without a fill value the block stays zeroed.
+        let assembly = assemble(".ORIG x3000\n.BLKW #2\n.END\n").unwrap();
+        assert_eq!(&assembly.data()[1..], &[0, 0]);
+    }
+
+    #[test]
+    fn test_blkw_rejects_non_positive_counts() {
+        let error = assemble(".ORIG x3000\n.BLKW #0\n.END\n").unwrap_err();
+        assert!(error.message().contains("positive word count"));
+
+        let error = assemble(".ORIG x3000\n.BLKW #-2\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("'.BLKW' expects a positive word count, got -2"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_stringz_concatenates_adjacent_literals() {
+        let two = assemble(".ORIG x3000\n.STRINGZ \"ab\" \"cd\"\n.END\n").unwrap();
+        let joined = assemble(".ORIG x3000\n.STRINGZ \"abcd\"\n.END\n").unwrap();
+        assert_eq!(two.data(), joined.data());
+        assert_eq!(two.data()[1..], [0x61, 0x62, 0x63, 0x64, 0]);
+
+        let three = assemble(".ORIG x3000\n.STRINGZ \"a\" \"b\" \"c\"\n.END\n").unwrap();
+        assert_eq!(three.data()[1..], [0x61, 0x62, 0x63, 0]);
+    }
+
+    #[test]
+    fn test_trap_aliases_emit_their_vectors() {
+        let source = ".ORIG x3000\nGETC\nOUT\nPUTS\nIN\nPUTSP\nHALT\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(
+            assembly.data()[1..],
+            [0xF020, 0xF021, 0xF022, 0xF023, 0xF024, 0xF025]
+        );
+
+        let error = assemble(".ORIG x3000\nHALT R0\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("does not take operands"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_nop_assembles_to_zero() {
+        let source = ".ORIG x3000\nNOP\nADD R0, R0, #1\nNOP\nBRnzp DONE\nNOP\nDONE TRAP x25\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(
+            assembly.data()[1..],
+            [0x0000, 0x1021, 0x0000, 0x0E01, 0x0000, 0xF025]
+        );
+
+        let error = assemble(".ORIG x3000\nNOP R0\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("does not take operands"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_stringp_is_an_alias_for_the_packed_directive() {
+        let assembly = assemble(".ORIG x3000\n.STRINGP \"Hi\"\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0x6948, 0x0000]);
+    }
+
+    #[test]
+    fn test_ret_assembles_to_jmp_r7() {
+        let assembly = assemble(".ORIG x3000\nRET\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0xC1C0]);
+
+        let error = assemble(".ORIG x3000\nRET R6\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("does not take operands"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_stringzp_packs_two_characters_per_word() {
+        // "Hi!" packs to the same words lc3tools produces.
+        let assembly = assemble(".ORIG x3000\n.STRINGZP \"Hi!\"\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0x6948, 0x0021, 0x0000]);
+
+        // Labels after the packed string account for the packed length.
+        let source =
+            ".ORIG x3000\nLD R0, AFTER\n.STRINGZP \"Hi\"\nAFTER .FILL #7\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.data()[1], 0x2002);
+    }
+
+    #[test]
+    fn test_stringz_decodes_escape_sequences() {
+        let assembly = assemble(".ORIG x3000\n.STRINGZ \"a\\nb\"\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..], [0x61, 0x0A, 0x62, 0x0000]);
+
+        let assembly =
+            assemble(".ORIG x3000\n.STRINGZ \"\\x41\\t\\\"quoted\\\"\"\n.END\n").unwrap();
+        assert_eq!(assembly.data()[1..4], [0x41, 0x09, 0x22]);
+    }
+
+    #[test]
+    fn test_invalid_escape_sequences_are_an_error() {
+        let error = assemble(".ORIG x3000\n.STRINGZ \"bad\\q\"\n.END\n").unwrap_err();
+        assert_eq!(error.message(), "Invalid escape sequence '\\q'");
+    }
+
+    #[test]
+    fn test_stringz_rejects_non_string_operands() {
+        let error = assemble(".ORIG x3000\n.STRINGZ \"a\" #5\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("expects a string as second operand"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    fn map_resolver(
+        files: &[(&str, &str)],
+    ) -> impl Fn(&str) -> Result<String, String> {
+        let files: HashMap<String, String> = files
+            .iter()
+            .map(|(name, content)| (name.to_string(), content.to_string()))
+            .collect();
+        move |path: &str| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| "file not found".to_string())
+        }
+    }
+
+    #[test]
+    fn test_include_splices_labels_into_the_section() {
+        let source = ".ORIG x3000\nLD R0, VALUE\nTRAP x25\n.INCLUDE \"data.asm\"\n.END\n";
+        let resolver = map_resolver(&[("data.asm", "VALUE .FILL x1234\n")]);
+        let assembly = assemble_with_resolver(source, resolver).unwrap();
+        assert_eq!(assembly.data()[1..], [0x2001, 0xF025, 0x1234]);
+    }
+
+    #[test]
+    fn test_include_can_nest() {
+        let source = ".ORIG x3000\n.INCLUDE \"a.asm\"\nTRAP x25\n.END\n";
+        let resolver = map_resolver(&[
+            ("a.asm", ".INCLUDE \"b.asm\"\n"),
+            ("b.asm", "ADD R0, R0, #1\n"),
+        ]);
+        let assembly = assemble_with_resolver(source, resolver).unwrap();
+        assert_eq!(assembly.data()[1..], [0x1021, 0xF025]);
+    }
+
+    #[test]
+    fn test_include_cycle_is_an_error() {
+        let source = ".ORIG x3000\n.INCLUDE \"a.asm\"\n.END\n";
+        let resolver = map_resolver(&[("a.asm", ".INCLUDE \"a.asm\"\n")]);
+        let error = assemble_with_resolver(source, resolver).unwrap_err();
+        assert!(
+            error.message().contains("Include cycle detected"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_missing_include_is_an_error() {
+        let source = ".ORIG x3000\n.INCLUDE \"nope.asm\"\n.END\n";
+        let error = assemble_with_resolver(source, map_resolver(&[])).unwrap_err();
+        assert!(
+            error.message().contains("Could not include \"nope.asm\""),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_macro_expands_to_hand_written_equivalent() {
+        let source = "\
+.ORIG x3000
+.MACRO PUSH reg
+ADD R6, R6, #-1
+STR reg, R6, #0
+.ENDM
+PUSH R3
+PUSH R4
+TRAP x25
+.END
+";
+        let expanded = assemble_with_resolver(source, map_resolver(&[])).unwrap();
+        let by_hand = assemble(
+            ".ORIG x3000\nADD R6, R6, #-1\nSTR R3, R6, #0\nADD R6, R6, #-1\nSTR R4, R6, #0\nTRAP x25\n.END\n",
+        )
+        .unwrap();
+        assert_eq!(expanded.data(), by_hand.data());
+    }
+
+    #[test]
+    fn test_macro_argument_count_mismatch_is_an_error() {
+        let source = ".ORIG x3000\n.MACRO PUSH reg\nSTR reg, R6, #0\n.ENDM\nPUSH\n.END\n";
+        let error = assemble_with_resolver(source, map_resolver(&[])).unwrap_err();
+        assert!(
+            error.message().contains("expects 1 argument(s), got 0"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_recursive_macro_hits_the_depth_limit() {
+        let source = ".ORIG x3000\n.MACRO LOOP\nLOOP\n.ENDM\nLOOP\n.END\n";
+        let error = assemble_with_resolver(source, map_resolver(&[])).unwrap_err();
+        assert!(
+            error.message().contains("depth limit"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_error_in_macro_body_names_the_invocation_site() {
+        let source = ".ORIG x3000\n.MACRO JUMP target\nBRnzp target\n.ENDM\nJUMP NOWHERE\n.END\n";
+        let error = assemble_with_resolver(source, map_resolver(&[])).unwrap_err();
+        assert!(
+            error
+                .message()
+                .contains("in expansion of macro 'JUMP' invoked on line 5"),
+            "unexpected message: {}",
+            error.message()
+        );
+        // The position points at the invocation, not the expanded text.
+        assert_eq!(error.line(), 5);
+        assert!(
+            error.to_string().contains("JUMP NOWHERE"),
+            "bad rendering: {}",
+            error
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_assembly_round_trips_through_json() {
+        let assembly = assemble(PUTS).unwrap();
+        let json = assembly.to_json();
+        // Deterministic output: serializing twice is byte-identical.
+        assert_eq!(json, assemble(PUTS).unwrap().to_json());
+        let restored = Assembly::from_json(&json).unwrap();
+        assert_eq!(restored.data(), assembly.data());
+        assert_eq!(restored.labels(), assembly.labels());
+        assert_eq!(restored.source_map(), assembly.source_map());
+    }
+
+    #[test]
+    fn test_lex_produces_a_flat_token_stream() {
+        let source = ".ORIG x3000\nLOOP ADD R0, R0, #1 ; bump\n.STRINGZ \"hi\"\n.END\n";
+        let tokens = lex(source).unwrap();
+        let flattened: Vec<_> = tokens.iter().map(|token| (token.kind, token.text)).collect();
+        assert_eq!(
+            flattened,
+            vec![
+                (TokenKind::Opcode, ".ORIG"),
+                (TokenKind::Immediate, "x3000"),
+                (TokenKind::Label, "LOOP"),
+                (TokenKind::Opcode, "ADD"),
+                (TokenKind::Register, "R0"),
+                (TokenKind::Register, "R0"),
+                (TokenKind::Immediate, "#1"),
+                (TokenKind::Comment, "; bump"),
+                (TokenKind::Opcode, ".STRINGZ"),
+                (TokenKind::String, "\"hi\""),
+                (TokenKind::Opcode, ".END"),
+            ]
+        );
+        assert_eq!(tokens[2].position.line_col(), (2, 1));
+    }
+
+    #[test]
+    fn test_bad_register_names_explain_the_valid_range() {
+        assert_eq!(
+            Register::from_str("R8"),
+            Err("register must be R0–R7, got 'R8'".to_string())
+        );
+        assert_eq!(
+            Register::from_str("RX"),
+            Err("register must be R0–R7, got 'RX'".to_string())
+        );
+        assert_eq!(
+            Register::from_str("R"),
+            Err("register must be R0–R7, got 'R'".to_string())
+        );
+        assert_eq!(Register::from_str("r3"), Ok(Register::R3));
+    }
+
+    #[test]
+    fn test_format_ast_renders_an_indented_outline() {
+        let source = ".ORIG x3000\nLOOP ADD R0, R0, #1\nBRp LOOP\nDONE .STRINGZ \"hi\"\n.END\n";
+        let ast = parse(source).unwrap();
+        let expected = "\
+SectionScope origin=x3000
+  Line
+    Label LOOP
+    Instruction ADD
+      Register R0
+      Register R0
+      Immediate x0001
+  Line
+    Instruction BRp
+      Label LOOP
+  Line
+    Label DONE
+    Instruction .STRINGZ
+      String \"hi\"
+";
+        assert_eq!(format_ast(&ast), expected);
+    }
+
+    #[test]
+    fn test_custom_pseudo_ops_emit_through_the_registry() {
+        struct WordSwap;
+        impl PseudoOp for WordSwap {
+            fn size(
+                &self,
+                _operands: &[AstNode<'_>],
+                _constants: &HashMap<String, Constant>,
+            ) -> u16 {
+                1
+            }
+            fn emit(
+                &self,
+                operands: &[AstNode<'_>],
+                _context: &EmitContext<'_>,
+            ) -> Result<Vec<u16>, String> {
+                match operands.first() {
+                    Some(AstNode::ImmediateOperand(value)) => Ok(vec![value.rotate_left(8)]),
+                    _ => Err("'.WORDSWAP' expects an immediate operand".to_string()),
+                }
+            }
+        }
+
+        let options =
+            AssembleOptions::default().with_pseudo_op(".WORDSWAP", std::rc::Rc::new(WordSwap));
+        let source = ".ORIG x3000\n.WORDSWAP x1234\n.END\n";
+        let assembly = assemble_with_options(source, &options).unwrap();
+        assert_eq!(assembly.words(), &[0x3412]);
+
+        // Without the registration the directive is an unknown opcode.
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("Unknown opcode '.wordswap'"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_label_operands_at_the_top_of_memory_do_not_overflow() {
+        // The PC the offset is measured from wraps to x0000 at xFFFF,
+        // just like the VM's does.
+        let assembly = assemble(".ORIG xFFFF\nL BRnzp L\n.END\n").unwrap();
+        assert_eq!(assembly.words(), &[0x0FFF]); // BRnzp #-1
+    }
+
+    #[test]
+    fn test_blkw_with_a_constant_count_sizes_following_labels() {
+        let source = ".ORIG x3000\n.EQU SIZE #3\nBUF .BLKW SIZE\nA .FILL A\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.labels()["BUF"].address, 0x3000);
+        assert_eq!(assembly.labels()["A"].address, 0x3003);
+        // The block really occupies three words and `.FILL A` holds A's
+        // actual address, not BUF's.
+        assert_eq!(assembly.words(), &[0, 0, 0, 0x3003]);
+    }
+
+    #[test]
+    fn test_blkw_count_constant_defined_after_use_is_an_error() {
+        // The count is unknown in pass 1 but resolves in pass 2, so the
+        // two passes disagree about the block's length; that must fail
+        // loudly instead of corrupting every later address.
+        let source = ".ORIG x3000\nBUF .BLKW SIZE\n.EQU SIZE #3\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("emitted 3 words but was sized as 0"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string_points_at_the_opening_quote() {
+        let source = ".ORIG x3000\n.STRINGZ \"oops\n.END\n";
+        let error = parse(source).unwrap_err();
+        assert_eq!(error.message(), "Unterminated string literal");
+        assert_eq!(error.line(), 2);
+        assert_eq!(error.column(), 10);
+    }
+
+    #[test]
+    fn test_trap_accepts_well_known_service_names() {
+        let named = assemble(".ORIG x3000\nTRAP HALT\n.END\n").unwrap();
+        let numeric = assemble(".ORIG x3000\nTRAP x25\n.END\n").unwrap();
+        assert_eq!(named.data(), numeric.data());
+
+        let named = assemble(".ORIG x3000\nTRAP getc\n.END\n").unwrap();
+        assert_eq!(named.words(), &[0xF020]);
+    }
+
+    #[test]
+    fn test_every_opcode_encodes_like_lc3tools() {
+        // One line per opcode, checked against the word lc3tools emits.
+        let cases: &[(&str, u16)] = &[
+            ("ADD R1, R2, R3", 0x1283),
+            ("ADD R1, R2, #5", 0x12A5),
+            ("AND R1, R2, R3", 0x5283),
+            ("AND R1, R2, #5", 0x52A5),
+            ("LOOP BRnzp LOOP", 0x0FFF),
+            ("JMP R2", 0xC080),
+            ("LOOP JSR LOOP", 0x4FFF),
+            ("JSRR R2", 0x4080),
+            ("LOOP LD R1, LOOP", 0x23FF),
+            ("LOOP LDI R1, LOOP", 0xA3FF),
+            ("LDR R1, R2, #5", 0x6285),
+            ("LOOP LEA R1, LOOP", 0xE3FF),
+            ("NOT R1, R2", 0x92BF),
+            ("RET", 0xC1C0),
+            ("RTI", 0x8000),
+            ("LOOP ST R1, LOOP", 0x33FF),
+            ("LOOP STI R1, LOOP", 0xB3FF),
+            ("STR R1, R2, #5", 0x7285),
+            ("TRAP x23", 0xF023),
+            ("GETC", 0xF020),
+            ("OUT", 0xF021),
+            ("PUTS", 0xF022),
+            ("IN", 0xF023),
+            ("PUTSP", 0xF024),
+            ("HALT", 0xF025),
+            ("NOP", 0x0000),
+        ];
+        for (line, expected) in cases {
+            let source = format!(".ORIG x3000\n{}\n.END\n", line);
+            let assembly = assemble(&source).unwrap();
+            assert_eq!(
+                assembly.words(),
+                &[*expected],
+                "wrong encoding for '{}'",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_trap_resolves_equ_constants_as_vectors() {
+        let source = ".ORIG x3000\n.EQU CHECKPOINT, x7F\nTRAP CHECKPOINT\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.words(), &[0xF07F]);
+    }
+
+    #[test]
+    fn test_unknown_trap_vector_gets_a_specific_error() {
+        let error = assemble(".ORIG x3000\nTRAP FOO\n.END\n").unwrap_err();
+        assert!(
+            error.message().contains("Unknown trap vector 'FOO'"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_symbol_lookup_by_name_and_by_address() {
+        let source = ".ORIG x3000\nSTART ADD R0, R0, #1\nLOOP ADD R0, R0, #1\nBRp LOOP\n.END\n";
+        let assembly = assemble(source).unwrap();
+        assert_eq!(assembly.symbol("START"), Some(0x3000));
+        assert_eq!(assembly.symbol("MISSING"), None);
+        // The nearest preceding label covers addresses past it (`LOOP+1`).
+        assert_eq!(assembly.symbol_at(0x3002), Some("LOOP"));
+        assert_eq!(assembly.symbol_at(0x2FFF), None);
+        let symbols: Vec<_> = assembly.symbols().collect();
+        assert_eq!(symbols, vec![("START", 0x3000), ("LOOP", 0x3001)]);
+    }
+
+    #[test]
+    fn test_conditional_assembly_keeps_only_the_active_branch() {
+        let body = ".IF DEBUG\nADD R0, R0, #1\nADD R0, R0, #2\n.ELSE\nADD R0, R0, #3\n.ENDIF\n.END\n";
+        let debug = format!(".ORIG x3000\n.EQU DEBUG, #1\n{}", body);
+        assert_eq!(assemble(&debug).unwrap().words().len(), 2);
+
+        // Without the definition the symbol is false and `.ELSE` wins.
+        let release = format!(".ORIG x3000\n{}", body);
+        let assembly = assemble(&release).unwrap();
+        assert_eq!(assembly.words(), &[0x1023]);
+    }
+
+    #[test]
+    fn test_unbalanced_if_is_a_positioned_error() {
+        let source = ".ORIG x3000\n.IF DEBUG\nADD R0, R0, #1\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("missing its matching '.ENDIF'"),
+            "unexpected message: {}",
+            error.message()
+        );
+        assert_eq!(error.line(), 2);
+    }
+
+    #[test]
+    fn test_endif_without_if_is_an_error() {
+        let source = ".ORIG x3000\n.ENDIF\n.END\n";
+        let error = assemble(source).unwrap_err();
+        assert!(
+            error.message().contains("'.ENDIF' without a matching '.IF'"),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn test_multibyte_characters_near_line_start_do_not_panic() {
+        // The first eight bytes of the comment line cut through a
+        // multi-byte character; the include scanner must not byte-slice
+        // it, since the grammar allows any character in comments.
+        let source = ".ORIG x3000\n;€€€ comment\nHALT\n.END\n";
+        let resolver = map_resolver(&[]);
+        assert!(assemble_with_resolver(source, resolver).is_ok());
+    }
+
+    #[test]
+    fn test_error_inside_include_names_the_file() {
+        let source = ".ORIG x3000\n.INCLUDE \"bad.asm\"\n.END\n";
+        let resolver = map_resolver(&[("bad.asm", "BRp NOWHERE\n")]);
+        let error = assemble_with_resolver(source, resolver).unwrap_err();
+        assert!(
+            error.message().contains("in file included as \"bad.asm\""),
+            "unexpected message: {}",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Color and column formatting for the command-line tools.
+//!
+//! With color off the rendered text is byte-identical to the colored
+//! output minus the escape codes, so snapshot tests only need to run in
+//! no-color mode.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// The `--color` flag the CLI tools accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Parses a `--color` flag value.
+    pub fn from_flag(value: &str) -> Result<Self, String> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            other => Err(format!(
+                "Invalid color choice '{}' (expected auto, always or never)",
+                other
+            )),
+        }
+    }
+
+    /// Whether escape codes should be emitted, honoring the `NO_COLOR`
+    /// convention and whether stderr is a terminal.
+    pub fn enabled(&self) -> bool {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        self.enabled_with(no_color, io::stderr().is_terminal())
+    }
+
+    /// `NO_COLOR` (any non-empty value) silences `Auto`, as does piped
+    /// output; an explicit `Always` wins over both.
+    pub(crate) fn enabled_with(&self, no_color: bool, is_terminal: bool) -> bool {
+        match self {
+            Self::Never => false,
+            Self::Always => true,
+            Self::Auto => is_terminal && !no_color,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Color {
+    Red,
+    Yellow,
+    Green,
+}
+
+impl Color {
+    fn code(&self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Yellow => "33",
+            Color::Green => "32",
+        }
+    }
+}
+
+/// Applies ANSI colors when the configured choice allows it and returns
+/// text unchanged otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    pub fn new(choice: ColorChoice) -> Self {
+        Self {
+            enabled: choice.enabled(),
+        }
+    }
+
+    pub fn paint(&self, color: Color, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Aligns rows of cells into columns: each column is padded to its widest
+/// cell, columns are separated by two spaces, and trailing padding is
+/// trimmed from each line.
+pub fn align_columns<S: AsRef<str>>(rows: &[Vec<S>]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            let width = cell.as_ref().chars().count();
+            if index == widths.len() {
+                widths.push(width);
+            } else {
+                widths[index] = widths[index].max(width);
+            }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let mut line = String::new();
+            for (index, cell) in row.iter().enumerate() {
+                if index > 0 {
+                    line.push_str("  ");
+                }
+                line.push_str(&format!("{:<width$}", cell.as_ref(), width = widths[index]));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
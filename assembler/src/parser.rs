@@ -0,0 +1,636 @@
+//! Translates the pest parse tree into the [`AstNode`] representation the
+//! emitter works with.
+
+use std::collections::HashMap;
+
+use pest::iterators::{Pair, Pairs};
+use pest::{Parser, Position};
+use pest_derive::Parser;
+
+use crate::{AstNode, ErrorWithPosition, Opcode, PositionContext, Register};
+
+#[derive(Parser)]
+#[grammar = "grammar.pest"]
+pub struct Lc3Parser;
+
+/// Parses LC-3 source into a list of top-level AST nodes (currently a single
+/// `SectionScope`), reporting the first error.
+pub fn parse(source: &str) -> Result<Vec<AstNode<'_>>, ErrorWithPosition> {
+    let (nodes, errors) = parse_collecting(source)?;
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(nodes),
+    }
+}
+
+/// Like [`parse`], but collects per-line errors (unknown opcodes, operand
+/// validation, bad immediates) instead of stopping at the first; offending
+/// lines are skipped. A failure of the pest grammar itself is still fatal.
+pub(crate) fn parse_collecting(
+    source: &str,
+) -> Result<(Vec<AstNode<'_>>, Vec<ErrorWithPosition>), ErrorWithPosition> {
+    let mut pairs = Lc3Parser::parse(Rule::program, source).map_err(|error| {
+        unterminated_string_error(source, &error)
+            .or_else(|| content_after_end_error(source, &error))
+            .unwrap_or_else(|| ErrorWithPosition::from_parse_error(error, source))
+    })?;
+    let program = pairs.next().expect("the program rule always matches");
+    let mut errors = Vec::new();
+    let nodes = traverse(program.into_inner(), &mut errors)?;
+    Ok((nodes, errors))
+}
+
+/// The lexical class of a [`Token`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Opcode,
+    Register,
+    Immediate,
+    Label,
+    Comment,
+    String,
+}
+
+/// One token of the source with its class, text and position, for syntax
+/// highlighters that do not need the full AST.
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    pub position: Position<'a>,
+}
+
+/// Tokenizes `source` into a flat stream in source order, without building
+/// the AST or resolving anything. Only a failure of the grammar itself is
+/// an error; unknown opcodes still come back as [`TokenKind::Opcode`].
+pub fn lex(source: &str) -> Result<Vec<Token<'_>>, ErrorWithPosition> {
+    let pairs = Lc3Parser::parse(Rule::program, source).map_err(|error| {
+        unterminated_string_error(source, &error)
+            .or_else(|| content_after_end_error(source, &error))
+            .unwrap_or_else(|| ErrorWithPosition::from_parse_error(error, source))
+    })?;
+    let mut tokens = Vec::new();
+    for pair in pairs {
+        collect_tokens(pair, &mut tokens);
+    }
+    Ok(tokens)
+}
+
+fn collect_tokens<'a>(pair: Pair<'a, Rule>, tokens: &mut Vec<Token<'a>>) {
+    let kind = match pair.as_rule() {
+        Rule::opcode | Rule::unknown_opcode => Some(TokenKind::Opcode),
+        Rule::register => Some(TokenKind::Register),
+        Rule::decimal | Rule::hex | Rule::expression => Some(TokenKind::Immediate),
+        Rule::label | Rule::adjusted_label => Some(TokenKind::Label),
+        Rule::comment => Some(TokenKind::Comment),
+        Rule::string_literal => Some(TokenKind::String),
+        // `.ORIG` and `.END` are literals inside their statement rules, not
+        // pairs of their own; synthesize their opcode token from the
+        // statement text and descend for the rest.
+        Rule::orig_statement | Rule::end_statement => {
+            tokens.push(Token {
+                kind: TokenKind::Opcode,
+                text: pair.as_str().split_whitespace().next().unwrap_or(""),
+                position: pair.as_span().start_pos(),
+            });
+            for inner in pair.into_inner() {
+                collect_tokens(inner, tokens);
+            }
+            return;
+        }
+        _ => None,
+    };
+    match kind {
+        Some(kind) => tokens.push(Token {
+            kind,
+            text: pair.as_str(),
+            position: pair.as_span().start_pos(),
+        }),
+        None => {
+            for inner in pair.into_inner() {
+                collect_tokens(inner, tokens);
+            }
+        }
+    }
+}
+
+/// A string literal missing its closing quote fails deep inside the grammar
+/// with an opaque "expected operand" error. When the failing line holds a
+/// quote that never closes, point at it and say so instead. (The old
+/// combinator-based assembler went as far as segfaulting on this input, so
+/// the diagnostic is load-bearing.)
+fn unterminated_string_error(
+    source: &str,
+    error: &pest::error::Error<Rule>,
+) -> Option<ErrorWithPosition> {
+    let offset = match error.location {
+        pest::error::InputLocation::Pos(pos) => pos,
+        pest::error::InputLocation::Span((start, _)) => start,
+    };
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = source[line_start..].lines().next().unwrap_or("");
+
+    // Walk the line tracking whether a string is open; an open quote at the
+    // end of the line is the culprit.
+    let mut open = None;
+    let mut chars = line.char_indices();
+    while let Some((index, character)) = chars.next() {
+        match character {
+            '"' => open = if open.is_some() { None } else { Some(index) },
+            '\\' if open.is_some() => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+
+    let position = Position::new(source, line_start + open?)?;
+    Some(ErrorWithPosition::new(
+        "Unterminated string literal",
+        position,
+    ))
+}
+
+/// Content after `.END` fails the grammar with a generic "expected EOI"
+/// error. When the failing line is real content (not a comment) and a
+/// completed `.END` precedes it, say what is actually wrong.
+fn content_after_end_error(
+    source: &str,
+    error: &pest::error::Error<Rule>,
+) -> Option<ErrorWithPosition> {
+    let offset = match error.location {
+        pest::error::InputLocation::Pos(pos) => pos,
+        pest::error::InputLocation::Span((start, _)) => start,
+    };
+    let line_start = source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = source[line_start..].lines().next().unwrap_or("").trim();
+    if line.is_empty() || line.starts_with(';') {
+        return None;
+    }
+    let is_end_line = |line: &str| {
+        let line = line.trim_start();
+        line.len() >= 4
+            && line[..4].eq_ignore_ascii_case(".END")
+            && !line[4..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !source[..line_start].lines().any(is_end_line) {
+        return None;
+    }
+    let position = Position::new(source, offset)?;
+    Some(ErrorWithPosition::new(
+        "Instruction found outside of a '.ORIG'/'.END' section",
+        position,
+    ))
+}
+
+/// A `.ORIG` parsed as an ordinary line means everything collected before
+/// it sits outside the section it was meant to open; the error points at
+/// the first such instruction.
+fn stray_orig_error<'a>(
+    line: &AstNode<'a>,
+    content: &[AstNode<'a>],
+) -> Option<ErrorWithPosition> {
+    let AstNode::Line {
+        instruction: Some(instruction),
+        ..
+    } = line
+    else {
+        return None;
+    };
+    let AstNode::PseudoInstruction { name, span, .. } = &**instruction else {
+        return None;
+    };
+    if !name.eq_ignore_ascii_case(".ORIG") {
+        return None;
+    }
+    let position = content
+        .iter()
+        .find_map(|node| {
+            let AstNode::Line {
+                instruction: Some(instruction),
+                ..
+            } = node
+            else {
+                return None;
+            };
+            match &**instruction {
+                AstNode::Instruction { span, .. } | AstNode::PseudoInstruction { span, .. } => {
+                    Some(span.start_pos())
+                }
+                _ => None,
+            }
+        })
+        .unwrap_or_else(|| span.start_pos());
+    Some(ErrorWithPosition::new(
+        "Instruction found outside of a '.ORIG'/'.END' section",
+        position,
+    ))
+}
+
+fn traverse<'a>(
+    pairs: Pairs<'a, Rule>,
+    errors: &mut Vec<ErrorWithPosition>,
+) -> Result<Vec<AstNode<'a>>, ErrorWithPosition> {
+    let mut nodes = Vec::new();
+    for pair in pairs {
+        if pair.as_rule() == Rule::section {
+            nodes.push(build_ast_from_section(pair, errors)?);
+        }
+    }
+    Ok(nodes)
+}
+
+fn build_ast_from_section<'a>(
+    pair: Pair<'a, Rule>,
+    errors: &mut Vec<ErrorWithPosition>,
+) -> Result<AstNode<'a>, ErrorWithPosition> {
+    let span = pair.as_span();
+    let mut origin = None;
+    let mut content = Vec::new();
+    // `.EQU` constants seen so far, so arithmetic immediates further down
+    // can use them; everything else resolves constants in the emitter.
+    let mut constants: HashMap<&str, u16> = HashMap::new();
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::orig_statement => {
+                let immediate = inner
+                    .into_inner()
+                    .find(|p| {
+                        matches!(p.as_rule(), Rule::decimal | Rule::hex | Rule::expression)
+                    })
+                    .expect("orig_statement always contains an immediate");
+                origin = Some(parse_immediate(&immediate, &constants)?);
+            }
+            Rule::line => match build_ast_from_line(inner, &constants) {
+                Ok(line) => {
+                    if let Some(error) = stray_orig_error(&line, &content) {
+                        errors.push(error);
+                        continue;
+                    }
+                    record_parse_time_constant(&line, &mut constants);
+                    content.push(line);
+                }
+                Err(error) => errors.push(error),
+            },
+            Rule::end_statement => {}
+            _ => unreachable!("unexpected rule inside section: {:?}", inner.as_rule()),
+        }
+    }
+    Ok(AstNode::SectionScope {
+        origin,
+        content,
+        span,
+    })
+}
+
+/// Remembers `.EQU` definitions so later expressions can fold them in.
+fn record_parse_time_constant<'a>(line: &AstNode<'a>, constants: &mut HashMap<&'a str, u16>) {
+    let AstNode::Line {
+        instruction: Some(instruction),
+        ..
+    } = line
+    else {
+        return;
+    };
+    let AstNode::Instruction {
+        opcode: Opcode::Equ,
+        operands,
+        ..
+    } = &**instruction
+    else {
+        return;
+    };
+    if let [AstNode::Label { name, .. }, AstNode::ImmediateOperand(value)] = operands.as_slice() {
+        constants.insert(name, *value);
+    }
+}
+
+/// Whether a label name also spells an opcode or trap-alias mnemonic. Such
+/// definitions are rejected: depending on the position they either shadow
+/// the instruction (`HALT` alone parses as a trap alias) or silently change
+/// how the line parses.
+fn collides_with_mnemonic(name: &str) -> bool {
+    const MNEMONICS: &[&str] = &[
+        "add", "and", "jmp", "jsr", "jsrr", "ld", "ldi", "ldr", "lea", "not", "ret", "rti",
+        "st", "sti", "str", "trap", "getc", "out", "puts", "in", "putsp", "halt", "nop",
+    ];
+    let lower = name.to_lowercase();
+    if MNEMONICS.contains(&lower.as_str()) {
+        return true;
+    }
+    // `BR` takes any in-order subset of the n/z/p flags; longer names like
+    // `BREAK` are legitimate labels.
+    match lower.strip_prefix("br") {
+        Some(flags) => {
+            let mut rest = flags;
+            for flag in ['n', 'z', 'p'] {
+                rest = rest.strip_prefix(flag).unwrap_or(rest);
+            }
+            rest.is_empty()
+        }
+        None => false,
+    }
+}
+
+fn build_ast_from_line<'a>(
+    pair: Pair<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<AstNode<'a>, ErrorWithPosition> {
+    let mut label = None;
+    let mut instruction = None;
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::label => {
+                if collides_with_mnemonic(inner.as_str()) {
+                    return Err(ErrorWithPosition::new(
+                        format!(
+                            "Label '{}' collides with the opcode of the same name",
+                            inner.as_str()
+                        ),
+                        inner.as_span().start_pos(),
+                    ));
+                }
+                label = Some(Box::new(AstNode::Label {
+                    name: inner.as_str(),
+                    span: inner.as_span(),
+                }))
+            }
+            Rule::instruction => {
+                instruction = Some(Box::new(build_ast_from_instruction(inner, constants)?))
+            }
+            Rule::unknown_instruction => {
+                let span = inner.as_span();
+                let mut parts = inner.into_inner();
+                let opcode = parts
+                    .next()
+                    .expect("unknown instructions always have an opcode token");
+                // Dot-prefixed names are deferred to the emitter, where the
+                // pseudo-op registry may know them; anything else is a typo
+                // right here. `Opcode::from` supplies the suggestion; the
+                // odd `Ok` case (e.g. a stray `BRq` counts as a BR
+                // spelling) still deserves the plain error.
+                if opcode.as_str().starts_with('.') {
+                    let mut operands = Vec::new();
+                    for operand in parts {
+                        operands.push(build_operand(operand, constants)?);
+                    }
+                    instruction = Some(Box::new(AstNode::PseudoInstruction {
+                        name: opcode.as_str(),
+                        operands,
+                        span,
+                    }));
+                    continue;
+                }
+                let message = match Opcode::from(opcode.as_str()) {
+                    Err(message) => message,
+                    Ok(_) => format!("Unknown opcode '{}'", opcode.as_str().to_lowercase()),
+                };
+                return Err(ErrorWithPosition::new(
+                    message,
+                    opcode.as_span().start_pos(),
+                ));
+            }
+            Rule::comment => {}
+            _ => unreachable!("unexpected rule inside line: {:?}", inner.as_rule()),
+        }
+    }
+    Ok(AstNode::Line { label, instruction })
+}
+
+fn build_ast_from_instruction<'a>(
+    pair: Pair<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<AstNode<'a>, ErrorWithPosition> {
+    let span = pair.as_span();
+    let mut inner = pair.into_inner();
+    let opcode_pair = inner.next().expect("instructions always have an opcode");
+    let opcode = Opcode::from(opcode_pair.as_str())
+        .with_position(opcode_pair.as_span().start_pos())?;
+    let mut operands = Vec::new();
+    for operand in inner {
+        operands.push(build_operand(operand, constants)?);
+    }
+    opcode
+        .validate_operands(&operands)
+        .with_position(span.start_pos())?;
+    Ok(AstNode::Instruction {
+        opcode,
+        operands,
+        span,
+    })
+}
+
+fn build_operand<'a>(
+    pair: Pair<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<AstNode<'a>, ErrorWithPosition> {
+    match pair.as_rule() {
+        Rule::register => {
+            let register =
+                Register::from_str(pair.as_str()).with_position(pair.as_span().start_pos())?;
+            Ok(AstNode::RegisterOperand(register))
+        }
+        Rule::decimal | Rule::hex | Rule::expression => {
+            Ok(AstNode::ImmediateOperand(parse_immediate(&pair, constants)?))
+        }
+        Rule::string_literal => {
+            let content = pair
+                .into_inner()
+                .next()
+                .expect("string literals always have content");
+            Ok(AstNode::StringLiteral(content.as_str()))
+        }
+        Rule::label => Ok(AstNode::Label {
+            name: pair.as_str(),
+            span: pair.as_span(),
+        }),
+        Rule::adjusted_label => {
+            let span = pair.as_span();
+            let mut inner = pair.into_inner();
+            let label = inner.next().expect("adjusted labels always have a label");
+            let adjustment = inner
+                .next()
+                .expect("adjusted labels always have an adjustment");
+            let offset = parse_label_adjustment(adjustment.as_str())
+                .with_position(adjustment.as_span().start_pos())?;
+            Ok(AstNode::AdjustedLabel {
+                name: label.as_str(),
+                offset,
+                span,
+            })
+        }
+        _ => unreachable!("unexpected operand rule: {:?}", pair.as_rule()),
+    }
+}
+
+fn parse_immediate<'a>(
+    pair: &Pair<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<u16, ErrorWithPosition> {
+    match pair.as_rule() {
+        Rule::decimal => {
+            parse_immediate_decimal(pair.as_str()).with_position(pair.as_span().start_pos())
+        }
+        Rule::hex => {
+            parse_immediate_hex(pair.as_str()).with_position(pair.as_span().start_pos())
+        }
+        Rule::expression => evaluate_expression(pair.clone(), constants),
+        _ => unreachable!("unexpected immediate rule: {:?}", pair.as_rule()),
+    }
+}
+
+/// Folds an arithmetic immediate (`#(1<<3)`, `#2*4+1`) into a single word.
+/// `*` and `/` bind tighter than `+` and `-`, which bind tighter than the
+/// shifts, mirroring C. Constants must come from an earlier `.EQU` line.
+fn evaluate_expression<'a>(
+    pair: Pair<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<u16, ErrorWithPosition> {
+    let position = pair.as_span().start_pos();
+    let text = pair.as_str().trim_end().to_string();
+    let value = evaluate_sequence(pair.into_inner(), constants)?;
+    if !(i16::MIN as i64..=u16::MAX as i64).contains(&value) {
+        return Err(ErrorWithPosition::new(
+            format!(
+                "Expression '{}' evaluates to {}, which does not fit into 16 bits",
+                text, value
+            ),
+            position,
+        ));
+    }
+    Ok(value as u16)
+}
+
+/// Evaluates a flat term/operator sequence with two precedence passes
+/// before the shifts are applied left to right.
+fn evaluate_sequence<'a>(
+    pairs: Pairs<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<i64, ErrorWithPosition> {
+    let mut values: Vec<i64> = Vec::new();
+    let mut ops: Vec<(&'a str, Position<'a>)> = Vec::new();
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::expr => values.push(evaluate_sequence(pair.into_inner(), constants)?),
+            Rule::expr_term => values.push(evaluate_term(pair, constants)?),
+            Rule::expr_op => ops.push((pair.as_str(), pair.as_span().start_pos())),
+            other => unreachable!("unexpected rule inside expression: {:?}", other),
+        }
+    }
+    for level in [&["*", "/"][..], &["+", "-"], &["<<", ">>"]] {
+        let mut index = 0;
+        while index < ops.len() {
+            if level.contains(&ops[index].0) {
+                values[index] = apply_operator(values[index], ops[index], values[index + 1])?;
+                values.remove(index + 1);
+                ops.remove(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+    Ok(values[0])
+}
+
+fn evaluate_term<'a>(
+    pair: Pair<'a, Rule>,
+    constants: &HashMap<&'a str, u16>,
+) -> Result<i64, ErrorWithPosition> {
+    let inner = pair
+        .into_inner()
+        .next()
+        .expect("expression terms always have content");
+    match inner.as_rule() {
+        Rule::expr_number => {
+            let text = inner.as_str();
+            let parsed = if text.starts_with('x') || text.starts_with('X') {
+                i64::from_str_radix(&text[1..], 16)
+            } else {
+                text.parse::<i64>()
+            };
+            parsed
+                .map_err(|_| format!("Invalid number '{}' in expression", text))
+                .with_position(inner.as_span().start_pos())
+        }
+        Rule::constant_ref => constants
+            .get(inner.as_str())
+            .map(|value| *value as i64)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown constant '{}' in expression (constants must be \
+                     defined with .EQU before use)",
+                    inner.as_str()
+                )
+            })
+            .with_position(inner.as_span().start_pos()),
+        Rule::expr => evaluate_sequence(inner.into_inner(), constants),
+        other => unreachable!("unexpected expression term: {:?}", other),
+    }
+}
+
+fn apply_operator<'a>(
+    lhs: i64,
+    (op, position): (&'a str, Position<'a>),
+    rhs: i64,
+) -> Result<i64, ErrorWithPosition> {
+    match op {
+        "+" => lhs.checked_add(rhs),
+        "-" => lhs.checked_sub(rhs),
+        "*" => lhs.checked_mul(rhs),
+        "/" => {
+            if rhs == 0 {
+                return Err(ErrorWithPosition::new(
+                    "Division by zero in expression",
+                    position,
+                ));
+            }
+            lhs.checked_div(rhs)
+        }
+        "<<" | ">>" => {
+            if !(0..=16).contains(&rhs) {
+                return Err(ErrorWithPosition::new(
+                    format!("Shift amount {} is out of range [0, 16]", rhs),
+                    position,
+                ));
+            }
+            Some(if op == "<<" { lhs << rhs } else { lhs >> rhs })
+        }
+        other => unreachable!("unexpected expression operator: {:?}", other),
+    }
+    .ok_or_else(|| ErrorWithPosition::new("Arithmetic overflow in expression", position))
+}
+
+/// Parses the `+1` / `-x10` part of an adjusted label operand.
+fn parse_label_adjustment(text: &str) -> Result<i16, String> {
+    let (sign, magnitude) = text.split_at(1);
+    let value = if magnitude.starts_with('x') || magnitude.starts_with('X') {
+        i16::from_str_radix(&magnitude[1..], 16)
+    } else {
+        magnitude.parse::<i16>()
+    }
+    .map_err(|_| format!("Invalid label adjustment '{}'", text))?;
+    Ok(if sign == "-" { -value } else { value })
+}
+
+/// Decimal immediates are signed and must fit into an i16; they are stored
+/// as their two's-complement word.
+fn parse_immediate_decimal(text: &str) -> Result<u16, String> {
+    text.trim_start_matches('#')
+        .parse::<i16>()
+        .map(|value| value as u16)
+        .map_err(|_| {
+            format!(
+                "Decimal immediate '{}' is out of range [-32768, 32767]",
+                text
+            )
+        })
+}
+
+/// Hex immediates are unsigned and must fit into a u16.
+fn parse_immediate_hex(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(&text[1..], 16)
+        .map_err(|_| format!("Hex immediate '{}' is out of range [x0, xFFFF]", text))
+}
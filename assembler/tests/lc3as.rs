@@ -0,0 +1,126 @@
+//! End-to-end tests for the `lc3as` binary, covering the stdin/stdout
+//! paths and the exit-code contract (1 for assembly errors, 2 for usage
+//! errors).
+
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+use assert_cmd::Command;
+
+fn lc3as() -> Command {
+    Command::cargo_bin("lc3as").unwrap()
+}
+
+/// A scratch path in the system temp directory, removed on drop.
+struct ScratchFile(PathBuf);
+
+impl ScratchFile {
+    fn new(name: &str) -> Self {
+        ScratchFile(env::temp_dir().join(format!("lc3as-test-{}-{}", process::id(), name)))
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn assembles_stdin_to_stdout() {
+    let output = lc3as()
+        .arg("-")
+        .write_stdin(".ORIG x3000\nHALT\n.END\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, vec![0x30, 0x00, 0xF0, 0x25]);
+}
+
+#[test]
+fn assembly_errors_exit_with_code_1() {
+    let output = lc3as()
+        .arg("-")
+        .write_stdin(".ORIG x3000\nADD R0, R0, R8\n.END\n")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn missing_arguments_exit_with_code_2() {
+    let output = lc3as().output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn print_ast_dumps_the_parse_tree() {
+    let output = lc3as()
+        .arg("--print-ast")
+        .arg("-")
+        .write_stdin(".ORIG x3000\nADD R0, R0, #1\n.END\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for rule in ["orig_statement", "instruction", "register", "decimal"] {
+        assert!(stdout.contains(rule), "no {} in output: {}", rule, stdout);
+    }
+}
+
+#[test]
+fn print_ir_dumps_the_ast_outline() {
+    let output = lc3as()
+        .arg("--print-ir")
+        .arg("-")
+        .write_stdin(".ORIG x3000\nADD R0, R0, #1\n.END\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("SectionScope origin=x3000"),
+        "unexpected output: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("Instruction ADD"),
+        "unexpected output: {}",
+        stdout
+    );
+}
+
+#[test]
+fn check_mode_reports_per_file_and_writes_nothing() {
+    let good = ScratchFile::new("good.asm");
+    let bad = ScratchFile::new("bad.asm");
+    fs::write(&good.0, ".ORIG x3000\nHALT\n.END\n").unwrap();
+    fs::write(&bad.0, ".ORIG x3000\nBR MISSING\n.END\n").unwrap();
+
+    let output = lc3as()
+        .arg("--check")
+        .arg(&good.0)
+        .arg(&bad.0)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("good.asm: OK"), "unexpected stdout: {}", stdout);
+    assert!(stdout.contains("1 of 2 files OK"), "unexpected stdout: {}", stdout);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("MISSING"), "unexpected stderr: {}", stderr);
+    assert!(!good.0.with_extension("obj").exists());
+    assert!(!good.0.with_extension("sym").exists());
+}
+
+#[test]
+fn two_positional_arguments_still_work() {
+    let input = ScratchFile::new("compat.asm");
+    let object = ScratchFile::new("compat.obj");
+    fs::write(&input.0, ".ORIG x3000\nHALT\n.END\n").unwrap();
+    lc3as().arg(&input.0).arg(&object.0).assert().success();
+    assert_eq!(fs::read(&object.0).unwrap(), vec![0x30, 0x00, 0xF0, 0x25]);
+    let sym = ScratchFile(object.0.with_extension("sym"));
+    assert!(sym.0.exists());
+}
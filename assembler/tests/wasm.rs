@@ -0,0 +1,26 @@
+//! Shapes of the wasm entry points, run with `wasm-pack test` or
+//! `cargo test --target wasm32-unknown-unknown`; compiles to nothing on
+//! native targets.
+
+#![cfg(target_arch = "wasm32")]
+
+use assembler::wasm::assemble_js;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn assembling_returns_words_origin_and_labels() {
+    let assembly = assemble_js(".ORIG x3000\nLOOP ADD R0, R0, #1\nBRp LOOP\n.END\n").unwrap();
+    assert_eq!(assembly.origin(), 0x3000);
+    assert_eq!(assembly.words(), vec![0x1021, 0x03FE]);
+    assert_eq!(assembly.label_names(), vec!["LOOP".to_string()]);
+    assert_eq!(assembly.label_addresses(), vec![0x3000]);
+    assert_eq!(assembly.source_map_addresses(), vec![0x3000, 0x3001]);
+}
+
+#[wasm_bindgen_test]
+fn errors_carry_line_and_column() {
+    let error = assemble_js(".ORIG x3000\nBRp NOWHERE\n.END\n").unwrap_err();
+    assert!(error.message().contains("NOWHERE"));
+    assert_eq!(error.line(), 2);
+    assert_eq!(error.column(), 1);
+}
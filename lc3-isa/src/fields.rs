@@ -0,0 +1,102 @@
+//! The signed immediate and offset fields of the LC-3 instruction word.
+//!
+//! The assembler's emitter and the VM's decoder both work with these fields;
+//! defining the widths and the mask/extend operations in one place
+//! guarantees that encode-then-decode is the identity for every value the
+//! assembler accepts.
+
+/// Width of the ADD/AND immediate field.
+pub const IMM5: u16 = 5;
+/// Width of the LDR/STR base offset field.
+pub const OFFSET6: u16 = 6;
+/// Width of the PC-relative offset used by BR, LD, LDI, LEA, ST and STI.
+pub const PC_OFFSET9: u16 = 9;
+/// Width of the PC-relative offset used by JSR.
+pub const PC_OFFSET11: u16 = 11;
+/// Width of the TRAP vector field (unsigned).
+pub const TRAPVECT8: u16 = 8;
+
+/// A mask covering the lowest `bits` bits.
+pub fn mask(bits: u16) -> u16 {
+    (1 << bits) - 1
+}
+
+/// The smallest value representable in a signed field of width `bits`.
+pub fn min_value(bits: u16) -> i16 {
+    -(1 << (bits - 1))
+}
+
+/// The largest value representable in a signed field of width `bits`.
+pub fn max_value(bits: u16) -> i16 {
+    (1 << (bits - 1)) - 1
+}
+
+/// Encodes a signed value into a field of width `bits`, erroring when the
+/// value does not fit.
+pub fn encode(value: i16, bits: u16) -> Result<u16, String> {
+    if value < min_value(bits) || value > max_value(bits) {
+        return Err(format!(
+            "Value {} does not fit into {} bits (valid range is {} to {})",
+            value,
+            bits,
+            min_value(bits),
+            max_value(bits)
+        ));
+    }
+    Ok(value as u16 & mask(bits))
+}
+
+/// Truncates a raw word to a field of width `bits`, discarding upper bits.
+pub fn truncate(value: u16, bits: u16) -> u16 {
+    value & mask(bits)
+}
+
+/// Sign-extends a field of width `bits` (the value in the lowest `bits`
+/// bits, already masked) to the full word. A zero-width field has no sign
+/// bit and extends to 0.
+pub fn sign_extend(x: u16, msb: u16) -> u16 {
+    if msb == 0 {
+        return 0;
+    }
+    if (x >> (msb - 1)) & 1 == 1 {
+        x | !mask(msb)
+    } else {
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_then_decode_is_the_identity() {
+        for bits in [IMM5, OFFSET6, PC_OFFSET9, PC_OFFSET11] {
+            for value in min_value(bits)..=max_value(bits) {
+                let encoded = encode(value, bits).unwrap();
+                assert_eq!(encoded & !mask(bits), 0, "{} bit encoding overflowed", bits);
+                assert_eq!(
+                    sign_extend(encoded, bits) as i16,
+                    value,
+                    "{} did not survive a {} bit round-trip",
+                    value,
+                    bits
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sign_extend_accepts_a_zero_width_field() {
+        // No sign bit to look at; must not underflow the shift.
+        assert_eq!(sign_extend(0, 0), 0);
+    }
+
+    #[test]
+    fn test_encode_rejects_values_outside_the_field() {
+        for bits in [IMM5, OFFSET6, PC_OFFSET9, PC_OFFSET11] {
+            assert!(encode(min_value(bits) - 1, bits).is_err());
+            assert!(encode(max_value(bits) + 1, bits).is_err());
+        }
+    }
+}
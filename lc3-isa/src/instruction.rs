@@ -0,0 +1,606 @@
+//! The LC-3 instruction table: one set of encodings shared by the
+//! assembler's emitter and the virtual machine's decoder, so the two cannot
+//! silently diverge.
+
+use std::fmt;
+
+use crate::fields;
+
+/// Register file indices. `PC` and `PSR` live in the same array as the
+/// general-purpose registers so the VM can address them uniformly;
+/// instruction operands only ever name `R0` through `R7`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registers {
+    R0 = 0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    PC,
+    PSR,
+}
+
+impl Registers {
+    pub fn from_u16_or_panic(value: u16) -> Registers {
+        match value {
+            0 => Registers::R0,
+            1 => Registers::R1,
+            2 => Registers::R2,
+            3 => Registers::R3,
+            4 => Registers::R4,
+            5 => Registers::R5,
+            6 => Registers::R6,
+            7 => Registers::R7,
+            _ => panic!("Invalid register {}", value),
+        }
+    }
+}
+
+/// The N/Z/P condition codes, valued as the bit each occupies in the PSR's
+/// low three bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionFlags {
+    Positive = 0b001,
+    Zero = 0b010,
+    Negative = 0b100,
+}
+
+impl ConditionFlags {
+    /// The flag that writing `value` to a register sets.
+    pub fn from_value(value: u16) -> ConditionFlags {
+        if value == 0 {
+            ConditionFlags::Zero
+        } else if value >> 15 == 1 {
+            ConditionFlags::Negative
+        } else {
+            ConditionFlags::Positive
+        }
+    }
+}
+
+/// The sixteen 4-bit opcodes, in encoding order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    BR,
+    ADD,
+    LD,
+    ST,
+    JSR,
+    AND,
+    LDR,
+    STR,
+    RTI,
+    NOT,
+    LDI,
+    STI,
+    JMP,
+    RES,
+    LEA,
+    TRAP,
+}
+
+impl Opcode {
+    pub fn from_u16(value: u16) -> Opcode {
+        match value {
+            0 => Opcode::BR,
+            1 => Opcode::ADD,
+            2 => Opcode::LD,
+            3 => Opcode::ST,
+            4 => Opcode::JSR,
+            5 => Opcode::AND,
+            6 => Opcode::LDR,
+            7 => Opcode::STR,
+            8 => Opcode::RTI,
+            9 => Opcode::NOT,
+            10 => Opcode::LDI,
+            11 => Opcode::STI,
+            12 => Opcode::JMP,
+            13 => Opcode::RES,
+            14 => Opcode::LEA,
+            15 => Opcode::TRAP,
+            _ => unreachable!("a 4-bit opcode cannot exceed 15"),
+        }
+    }
+}
+
+/// Helpers for pulling fields out of raw instruction words.
+pub trait BitTools {
+    fn to_register(self, lowest_bit: u16) -> Registers;
+    fn to_immediate(self, bits: u16) -> u16;
+    fn bit_set(self, bit: u16) -> bool;
+}
+
+impl BitTools for u16 {
+    fn to_register(self, lowest_bit: u16) -> Registers {
+        Registers::from_u16_or_panic((self >> lowest_bit) & 0x7)
+    }
+
+    fn to_immediate(self, bits: u16) -> u16 {
+        fields::sign_extend(fields::truncate(self, bits), bits)
+    }
+
+    fn bit_set(self, bit: u16) -> bool {
+        (self >> bit) & 1 == 1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    AddImmediate { dr: Registers, sr1: Registers, imm5: u16 },
+    AddRegister { dr: Registers, sr1: Registers, sr2: Registers },
+    AndImmediate { dr: Registers, sr1: Registers, imm5: u16 },
+    AndRegister { dr: Registers, sr1: Registers, sr2: Registers },
+    Br { n: bool, z: bool, p: bool, pc_offset9: u16 },
+    Jmp { base_r: Registers },
+    Jsr { pc_offset11: u16 },
+    JsrRegister { base_r: Registers },
+    Ld { dr: Registers, pc_offset9: u16 },
+    Ldi { dr: Registers, pc_offset9: u16 },
+    Ldr { dr: Registers, base_r: Registers, offset6: u16 },
+    Lea { dr: Registers, pc_offset9: u16 },
+    Not { dr: Registers, sr: Registers },
+    Rti {},
+    /// The unused 0b1101 opcode; executing it raises an illegal-opcode
+    /// error instead of crashing the VM.
+    Reserved {},
+    St { sr: Registers, pc_offset9: u16 },
+    Sti { sr: Registers, pc_offset9: u16 },
+    Str { sr: Registers, base_r: Registers, offset6: u16 },
+    Trap { trapvect8: u16 },
+    /// A word that violates one of the ISA's fixed-field constraints; only
+    /// produced by [`Instruction::from_raw_strict`].
+    Malformed { raw: u16, reason: &'static str },
+}
+
+impl Instruction {
+    pub fn from_raw(raw: u16) -> Instruction {
+        match Opcode::from_u16(raw >> 12) {
+            Opcode::ADD if raw.bit_set(5) => Instruction::AddImmediate {
+                dr: raw.to_register(9),
+                sr1: raw.to_register(6),
+                imm5: raw.to_immediate(5),
+            },
+            Opcode::ADD => Instruction::AddRegister {
+                dr: raw.to_register(9),
+                sr1: raw.to_register(6),
+                sr2: raw.to_register(0),
+            },
+            Opcode::AND if raw.bit_set(5) => Instruction::AndImmediate {
+                dr: raw.to_register(9),
+                sr1: raw.to_register(6),
+                imm5: raw.to_immediate(5),
+            },
+            Opcode::AND => Instruction::AndRegister {
+                dr: raw.to_register(9),
+                sr1: raw.to_register(6),
+                sr2: raw.to_register(0),
+            },
+            Opcode::BR => Instruction::Br {
+                n: raw.bit_set(11),
+                z: raw.bit_set(10),
+                p: raw.bit_set(9),
+                pc_offset9: raw.to_immediate(9),
+            },
+            Opcode::JMP => Instruction::Jmp {
+                base_r: raw.to_register(6),
+            },
+            Opcode::JSR if raw.bit_set(11) => Instruction::Jsr {
+                pc_offset11: raw.to_immediate(11),
+            },
+            Opcode::JSR => Instruction::JsrRegister {
+                base_r: raw.to_register(6),
+            },
+            Opcode::LD => Instruction::Ld {
+                dr: raw.to_register(9),
+                pc_offset9: raw.to_immediate(9),
+            },
+            Opcode::LDI => Instruction::Ldi {
+                dr: raw.to_register(9),
+                pc_offset9: raw.to_immediate(9),
+            },
+            Opcode::LDR => Instruction::Ldr {
+                dr: raw.to_register(9),
+                base_r: raw.to_register(6),
+                offset6: raw.to_immediate(6),
+            },
+            Opcode::LEA => Instruction::Lea {
+                dr: raw.to_register(9),
+                pc_offset9: raw.to_immediate(9),
+            },
+            Opcode::NOT => Instruction::Not {
+                dr: raw.to_register(9),
+                sr: raw.to_register(6),
+            },
+            Opcode::RTI => Instruction::Rti {},
+            Opcode::RES => Instruction::Reserved {},
+            Opcode::ST => Instruction::St {
+                sr: raw.to_register(9),
+                pc_offset9: raw.to_immediate(9),
+            },
+            Opcode::STI => Instruction::Sti {
+                sr: raw.to_register(9),
+                pc_offset9: raw.to_immediate(9),
+            },
+            Opcode::STR => Instruction::Str {
+                sr: raw.to_register(9),
+                base_r: raw.to_register(6),
+                offset6: raw.to_immediate(6),
+            },
+            Opcode::TRAP => Instruction::Trap {
+                trapvect8: raw & 0xFF,
+            },
+        }
+    }
+
+    /// Like [`Instruction::from_raw`], but validates the fixed fields the
+    /// ISA specifies (NOT's all-ones low bits, the zero fields of JMP, JSRR,
+    /// RTI and TRAP, and the reserved opcode), classifying violations as
+    /// [`Instruction::Malformed`] instead of a plausible instruction.
+    pub fn from_raw_strict(raw: u16) -> Instruction {
+        let malformed = |reason| Instruction::Malformed { raw, reason };
+        match Opcode::from_u16(raw >> 12) {
+            Opcode::ADD | Opcode::AND if !raw.bit_set(5) && raw & 0x18 != 0 => {
+                malformed("register-mode ADD/AND requires bits [4:3] to be zero")
+            }
+            Opcode::NOT if raw & 0x3F != 0x3F => {
+                malformed("NOT requires bits [5:0] to be all ones")
+            }
+            Opcode::JMP if raw & 0x0E3F != 0 => {
+                malformed("JMP requires bits [11:9] and [5:0] to be zero")
+            }
+            Opcode::JSR if !raw.bit_set(11) && raw & 0x063F != 0 => {
+                malformed("JSRR requires bits [10:9] and [5:0] to be zero")
+            }
+            Opcode::RTI if raw & 0x0FFF != 0 => {
+                malformed("RTI requires bits [11:0] to be zero")
+            }
+            Opcode::TRAP if raw & 0x0F00 != 0 => {
+                malformed("TRAP requires bits [11:8] to be zero")
+            }
+            Opcode::RES => malformed("the 0b1101 opcode is reserved"),
+            _ => Instruction::from_raw(raw),
+        }
+    }
+
+    /// Packs the instruction back into its memory word. For every word with
+    /// a well-formed encoding this is the inverse of
+    /// [`Instruction::from_raw`]; immediate and offset fields are truncated
+    /// to their widths, so the sign-extended values `from_raw` produces
+    /// encode back to the same bits.
+    pub fn to_raw(&self) -> u16 {
+        let field = fields::truncate;
+        match *self {
+            Instruction::AddImmediate { dr, sr1, imm5 } => {
+                0x1000 | (dr as u16) << 9 | (sr1 as u16) << 6 | 0x20 | field(imm5, fields::IMM5)
+            }
+            Instruction::AddRegister { dr, sr1, sr2 } => {
+                0x1000 | (dr as u16) << 9 | (sr1 as u16) << 6 | sr2 as u16
+            }
+            Instruction::AndImmediate { dr, sr1, imm5 } => {
+                0x5000 | (dr as u16) << 9 | (sr1 as u16) << 6 | 0x20 | field(imm5, fields::IMM5)
+            }
+            Instruction::AndRegister { dr, sr1, sr2 } => {
+                0x5000 | (dr as u16) << 9 | (sr1 as u16) << 6 | sr2 as u16
+            }
+            Instruction::Br { n, z, p, pc_offset9 } => {
+                (n as u16) << 11
+                    | (z as u16) << 10
+                    | (p as u16) << 9
+                    | field(pc_offset9, fields::PC_OFFSET9)
+            }
+            Instruction::Jmp { base_r } => 0xC000 | (base_r as u16) << 6,
+            Instruction::Jsr { pc_offset11 } => 0x4800 | field(pc_offset11, fields::PC_OFFSET11),
+            Instruction::JsrRegister { base_r } => 0x4000 | (base_r as u16) << 6,
+            Instruction::Ld { dr, pc_offset9 } => {
+                0x2000 | (dr as u16) << 9 | field(pc_offset9, fields::PC_OFFSET9)
+            }
+            Instruction::Ldi { dr, pc_offset9 } => {
+                0xA000 | (dr as u16) << 9 | field(pc_offset9, fields::PC_OFFSET9)
+            }
+            Instruction::Ldr { dr, base_r, offset6 } => {
+                0x6000 | (dr as u16) << 9 | (base_r as u16) << 6 | field(offset6, fields::OFFSET6)
+            }
+            Instruction::Lea { dr, pc_offset9 } => {
+                0xE000 | (dr as u16) << 9 | field(pc_offset9, fields::PC_OFFSET9)
+            }
+            Instruction::Not { dr, sr } => 0x9000 | (dr as u16) << 9 | (sr as u16) << 6 | 0x3F,
+            Instruction::Rti {} => 0x8000,
+            Instruction::Reserved {} => 0xD000,
+            Instruction::St { sr, pc_offset9 } => {
+                0x3000 | (sr as u16) << 9 | field(pc_offset9, fields::PC_OFFSET9)
+            }
+            Instruction::Sti { sr, pc_offset9 } => {
+                0xB000 | (sr as u16) << 9 | field(pc_offset9, fields::PC_OFFSET9)
+            }
+            Instruction::Str { sr, base_r, offset6 } => {
+                0x7000 | (sr as u16) << 9 | (base_r as u16) << 6 | field(offset6, fields::OFFSET6)
+            }
+            Instruction::Trap { trapvect8 } => 0xF000 | field(trapvect8, fields::TRAPVECT8),
+            Instruction::Malformed { raw, .. } => raw,
+        }
+    }
+
+    /// Renders the instruction as canonical assembly text. `addr` is the
+    /// address the instruction lives at; it is needed to resolve PC-relative
+    /// offsets into the absolute targets shown in the output.
+    pub fn to_assembly(&self, addr: u16) -> String {
+        self.render(Some(addr))
+    }
+
+    /// Shared rendering for [`Instruction::to_assembly`] and the `Display`
+    /// impl. Without an address, PC-relative operands fall back to their raw
+    /// signed offsets.
+    fn render(&self, addr: Option<u16>) -> String {
+        let target = |offset: u16| match addr {
+            Some(addr) => format!("x{:04X}", addr.wrapping_add(1).wrapping_add(offset)),
+            None => format!("#{}", offset as i16),
+        };
+        match *self {
+            Instruction::AddImmediate { dr, sr1, imm5 } => {
+                format!("ADD {:?}, {:?}, #{}", dr, sr1, imm5 as i16)
+            }
+            Instruction::AddRegister { dr, sr1, sr2 } => {
+                format!("ADD {:?}, {:?}, {:?}", dr, sr1, sr2)
+            }
+            Instruction::AndImmediate { dr, sr1, imm5 } => {
+                format!("AND {:?}, {:?}, #{}", dr, sr1, imm5 as i16)
+            }
+            Instruction::AndRegister { dr, sr1, sr2 } => {
+                format!("AND {:?}, {:?}, {:?}", dr, sr1, sr2)
+            }
+            Instruction::Br { n, z, p, pc_offset9 } => {
+                let mut flags = String::new();
+                if n {
+                    flags.push('n');
+                }
+                if z {
+                    flags.push('z');
+                }
+                if p {
+                    flags.push('p');
+                }
+                format!("BR{} {}", flags, target(pc_offset9))
+            }
+            Instruction::Jmp { base_r } => format!("JMP {:?}", base_r),
+            Instruction::Jsr { pc_offset11 } => format!("JSR {}", target(pc_offset11)),
+            Instruction::JsrRegister { base_r } => format!("JSRR {:?}", base_r),
+            Instruction::Ld { dr, pc_offset9 } => {
+                format!("LD {:?}, {}", dr, target(pc_offset9))
+            }
+            Instruction::Ldi { dr, pc_offset9 } => {
+                format!("LDI {:?}, {}", dr, target(pc_offset9))
+            }
+            Instruction::Ldr { dr, base_r, offset6 } => {
+                format!("LDR {:?}, {:?}, #{}", dr, base_r, offset6 as i16)
+            }
+            Instruction::Lea { dr, pc_offset9 } => {
+                format!("LEA {:?}, {}", dr, target(pc_offset9))
+            }
+            Instruction::Not { dr, sr } => format!("NOT {:?}, {:?}", dr, sr),
+            Instruction::Rti {} => "RTI".to_string(),
+            Instruction::Reserved {} => "RES".to_string(),
+            Instruction::St { sr, pc_offset9 } => {
+                format!("ST {:?}, {}", sr, target(pc_offset9))
+            }
+            Instruction::Sti { sr, pc_offset9 } => {
+                format!("STI {:?}, {}", sr, target(pc_offset9))
+            }
+            Instruction::Str { sr, base_r, offset6 } => {
+                format!("STR {:?}, {:?}, #{}", sr, base_r, offset6 as i16)
+            }
+            Instruction::Trap { trapvect8 } => format!("TRAP x{:02X}", trapvect8),
+            Instruction::Malformed { raw, reason } => {
+                format!(".FILL x{:04X}  ; {}", raw, reason)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(None))
+    }
+}
+
+/// Disassembles a block of memory words loaded at `origin`, one instruction
+/// per line with its address in the left column.
+pub fn disassemble(words: &[u16], origin: u16) -> String {
+    let mut output = String::new();
+    for (index, word) in words.iter().enumerate() {
+        let addr = origin.wrapping_add(index as u16);
+        output.push_str(&format!(
+            "x{:04X}  {}\n",
+            addr,
+            Instruction::from_raw_strict(*word).to_assembly(addr)
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_assembly_renders_canonical_mnemonics() {
+        assert_eq!(Instruction::from_raw(0x1025).to_assembly(0x3000), "ADD R0, R0, #5");
+        assert_eq!(Instruction::from_raw(0x103F).to_assembly(0x3000), "ADD R0, R0, #-1");
+        assert_eq!(Instruction::from_raw(0x5020).to_assembly(0x3000), "AND R0, R0, #0");
+        assert_eq!(Instruction::from_raw(0x9A7F).to_assembly(0x3000), "NOT R5, R1");
+        assert_eq!(Instruction::from_raw(0xF025).to_assembly(0x3000), "TRAP x25");
+    }
+
+    #[test]
+    fn test_to_assembly_resolves_pc_relative_targets() {
+        // BRp with an offset of -2 at x3003 branches to x3002.
+        assert_eq!(Instruction::from_raw(0x03FE).to_assembly(0x3003), "BRp x3002");
+        assert_eq!(Instruction::from_raw(0x2001).to_assembly(0x3000), "LD R0, x3002");
+        assert_eq!(Instruction::from_raw(0x4810).to_assembly(0x3000), "JSR x3011");
+    }
+
+    #[test]
+    fn test_to_immediate_round_trips_encoded_fields() {
+        use crate::fields;
+        // Every value the assembler's encoder accepts must decode back to
+        // itself through `to_immediate`.
+        for bits in [fields::IMM5, fields::OFFSET6, fields::PC_OFFSET9, fields::PC_OFFSET11] {
+            for value in fields::min_value(bits)..=fields::max_value(bits) {
+                let encoded = fields::encode(value, bits).unwrap();
+                assert_eq!(encoded.to_immediate(bits) as i16, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reserved_opcode_decodes_without_panicking() {
+        assert_eq!(Instruction::from_raw(0xD000), Instruction::Reserved {});
+        assert_eq!(Instruction::from_raw(0xDEAD), Instruction::Reserved {});
+    }
+
+    #[test]
+    fn test_to_raw_matches_hand_packed_words() {
+        let cases: &[u16] = &[
+            0x1025, // ADD R0, R0, #5
+            0x103F, // ADD R0, R0, #-1
+            0x1041, // ADD R0, R1, R1
+            0x5020, // AND R0, R0, #0
+            0x0FFE, // BRnzp #-2
+            0xC1C0, // RET
+            0x4810, // JSR #16
+            0x4040, // JSRR R1
+            0x6481, // LDR R2, R2, #1
+            0x967F, // NOT R3, R1
+            0x8000, // RTI
+            0x7E3F, // STR R7, R0, #-1
+            0xF025, // TRAP x25
+        ];
+        for raw in cases {
+            assert_eq!(Instruction::from_raw(*raw).to_raw(), *raw, "x{:04X}", raw);
+        }
+    }
+
+    #[test]
+    fn test_from_raw_to_raw_round_trips_random_words() {
+        // No `rand` dependency; a xorshift generator is plenty of coverage.
+        let mut state: u32 = 0x2A65_3FC1;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u16
+        };
+        for _ in 0..5000 {
+            let word = next();
+            let masked = mask_dont_care_bits(word);
+            assert_eq!(
+                Instruction::from_raw(masked).to_raw(),
+                masked,
+                "x{:04X} (from x{:04X}) did not round-trip",
+                masked,
+                word
+            );
+        }
+    }
+
+    /// Forces the bits `from_raw` ignores to the values `to_raw` emits, so
+    /// the round-trip comparison only covers bits the encoding carries:
+    /// the spec's zero fields, NOT's all-ones low bits, and everything
+    /// below the reserved opcode.
+    fn mask_dont_care_bits(word: u16) -> u16 {
+        match Opcode::from_u16(word >> 12) {
+            Opcode::ADD | Opcode::AND if !word.bit_set(5) => word & !0x18,
+            Opcode::NOT => word | 0x3F,
+            Opcode::JMP => word & !0x0E3F,
+            Opcode::JSR if !word.bit_set(11) => word & !0x063F,
+            Opcode::RTI | Opcode::RES => word & 0xF000,
+            Opcode::TRAP => word & !0x0F00,
+            _ => word,
+        }
+    }
+
+    #[test]
+    fn test_decoding_is_total_over_the_whole_word_space() {
+        // The REPL's source pane decodes arbitrary, possibly uninitialized
+        // memory words, so neither decoding nor rendering may ever panic.
+        for raw in 0..=u16::MAX {
+            let _ = Instruction::from_raw(raw).to_assembly(0x3000);
+            let strict = Instruction::from_raw_strict(raw);
+            let rendered = strict.to_assembly(0x3000);
+            if matches!(strict, Instruction::Malformed { .. }) {
+                assert!(
+                    rendered.starts_with(".FILL"),
+                    "x{:04X} rendered as {:?}",
+                    raw,
+                    rendered
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_condition_flags_from_value() {
+        assert_eq!(ConditionFlags::from_value(0), ConditionFlags::Zero);
+        assert_eq!(ConditionFlags::from_value(1), ConditionFlags::Positive);
+        assert_eq!(ConditionFlags::from_value(0x8000), ConditionFlags::Negative);
+    }
+
+    #[test]
+    fn test_display_uses_raw_offsets() {
+        assert_eq!(format!("{}", Instruction::from_raw(0x03FE)), "BRp #-2");
+        assert_eq!(format!("{}", Instruction::from_raw(0x1025)), "ADD R0, R0, #5");
+        assert_eq!(format!("{}", Instruction::from_raw(0x8000)), "RTI");
+        assert_eq!(format!("{}", Instruction::from_raw(0xF025)), "TRAP x25");
+    }
+
+    #[test]
+    fn test_disassemble_lists_one_instruction_per_line() {
+        let listing = disassemble(&[0x1025, 0xF025], 0x3000);
+        assert_eq!(listing, "x3000  ADD R0, R0, #5\nx3001  TRAP x25\n");
+    }
+
+    #[test]
+    fn test_strict_decode_flags_fixed_field_violations() {
+        // (word, whether strict decoding must reject it)
+        let cases: &[(u16, bool)] = &[
+            (0x903E, true),  // NOT with bits [5:0] != 0b111111
+            (0x967F, false), // well-formed NOT
+            (0xC1C1, true),  // JMP with a non-zero low field
+            (0xC1C0, false), // RET
+            (0x4041, true),  // JSRR with a non-zero low field
+            (0x4040, false), // well-formed JSRR
+            (0x8001, true),  // RTI with non-zero operand bits
+            (0x8000, false),
+            (0x1048, true),  // register-mode ADD with bit 3 set
+            (0x1041, false),
+            (0xF125, true),  // TRAP with non-zero bits [11:8]
+            (0xF025, false),
+            (0xD000, true),  // reserved opcode
+        ];
+        for (raw, rejected) in cases {
+            let strict = Instruction::from_raw_strict(*raw);
+            if *rejected {
+                assert!(
+                    matches!(strict, Instruction::Malformed { .. }),
+                    "x{:04X} should be malformed, got {:?}",
+                    raw,
+                    strict
+                );
+            } else {
+                // Lenient and strict decoding agree on well-formed words.
+                assert_eq!(strict, Instruction::from_raw(*raw));
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_words_disassemble_as_fill() {
+        let listing = disassemble(&[0x903E], 0x3000);
+        assert_eq!(
+            listing,
+            "x3000  .FILL x903E  ; NOT requires bits [5:0] to be all ones\n"
+        );
+    }
+}
@@ -0,0 +1,5 @@
+//! Definitions of the LC-3 instruction set shared between the assembler and
+//! the virtual machine, so encoding and decoding cannot silently diverge.
+
+pub mod fields;
+pub mod instruction;
@@ -0,0 +1,6 @@
+; Adds two immediates into R0, then halts.
+.ORIG x3000
+ADD R0, R0, #5
+ADD R0, R0, #10
+TRAP x25
+.END
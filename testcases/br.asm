@@ -0,0 +1,8 @@
+; Counts R0 down from 5 in a branch loop.
+.ORIG x3000
+AND R0, R0, #0
+ADD R0, R0, #5
+LOOP ADD R0, R0, #-1
+BRp LOOP
+TRAP x25
+.END
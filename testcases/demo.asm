@@ -0,0 +1,27 @@
+; Bundled demo for `lc3vm --demo`: prints a prompt via the display
+; registers, then echoes every typed key. Talks to the devices directly so
+; it needs no OS image.
+.ORIG x3000
+MAIN    LEA R1, PROMPT
+PRINT   LDR R2, R1, #0          ; write the prompt one character at a time
+        BRz WAIT
+POLL_D  LDI R3, DSRP            ; wait until the display is ready
+        BRzp POLL_D
+        STI R2, DDRP
+        ADD R1, R1, #1
+        BRnzp PRINT
+WAIT    LDI R2, KBSRP           ; wait for a key press
+        BRzp WAIT
+        LDI R2, KBDRP
+        AND R3, R3, #0          ; acknowledge the key
+        STI R3, KBSRP
+POLL_E  LDI R3, DSRP            ; echo it back
+        BRzp POLL_E
+        STI R2, DDRP
+        BRnzp WAIT
+KBSRP   .FILL xFE00
+KBDRP   .FILL xFE02
+DSRP    .FILL xFE04
+DDRP    .FILL xFE06
+PROMPT  .STRINGZ "Echo demo: type keys and they come right back\n> "
+.END
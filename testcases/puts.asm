@@ -0,0 +1,7 @@
+; Prints a greeting via the PUTS trap.
+.ORIG x3000
+LEA R0, MESSAGE
+TRAP x22
+TRAP x25
+MESSAGE .STRINGZ "Hello, World!"
+.END
@@ -0,0 +1,146 @@
+//! Assembles an LC-3 source file and runs it in one step, for quick
+//! experiments where producing an object file first is just ceremony.
+//!
+//! Exit status: 0 when the program halts (and all `.ASSERT` checkpoints
+//! pass), 1 for usage, read, assembly, or assertion failures, 2 when the
+//! program misbehaves at runtime or exhausts the tick cap.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use virtual_machine::load_words;
+use virtual_machine::peripherals::{AutomatedKeyboard, Peripheral, TerminalDisplay};
+use virtual_machine::repl::{format_branch_table, parse_address, run_until, StopReason};
+use virtual_machine::state::{Registers, VmState};
+
+/// Safety cap so a runaway program eventually returns to the shell.
+const DEFAULT_TICK_CAP: u64 = 50_000_000;
+
+struct RunOptions {
+    source: Option<PathBuf>,
+    input: Option<String>,
+    entrypoint: Option<u16>,
+    tick_cap: u64,
+    report: bool,
+    builtin_traps: bool,
+}
+
+fn parse_options() -> RunOptions {
+    let mut options = RunOptions {
+        source: None,
+        input: None,
+        entrypoint: None,
+        tick_cap: DEFAULT_TICK_CAP,
+        report: false,
+        builtin_traps: true,
+    };
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--report") => options.report = true,
+            Some("--no-builtin-traps") => options.builtin_traps = false,
+            Some("--input") => {
+                let value = args.next().expect("--input requires a string");
+                options.input = Some(value.to_string_lossy().into_owned());
+            }
+            Some("--entrypoint") | Some("-e") => {
+                let value = args.next().expect("--entrypoint requires an address");
+                options.entrypoint = Some(
+                    parse_address(&value.to_string_lossy()).expect("invalid entrypoint address"),
+                );
+            }
+            Some("--tick-cap") => {
+                let value = args.next().expect("--tick-cap requires a number");
+                options.tick_cap = value
+                    .to_string_lossy()
+                    .parse()
+                    .expect("invalid tick cap");
+            }
+            _ => options.source = Some(PathBuf::from(arg)),
+        }
+    }
+    options
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+    let options = parse_options();
+
+    let Some(path) = &options.source else {
+        eprintln!(
+            "Usage: lc3run <source.asm> [--input <text>] [--entrypoint <addr>] \
+             [--tick-cap <n>] [--report] [--no-builtin-traps]"
+        );
+        return ExitCode::FAILURE;
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("could not read \"{}\": {}", path.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let assembly = match assembler::assemble_all_diagnostics(&source) {
+        Ok(assembly) => assembly,
+        Err(diagnostics) => {
+            eprintln!("{}", diagnostics);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut state = VmState::new();
+    // Textbook programs expect GETC/OUT/PUTS/IN/PUTSP to just work, so the
+    // built-in handlers stand in for an OS image unless --no-builtin-traps
+    // asks for a bare machine.
+    state.use_builtin_traps(options.builtin_traps);
+    load_words(assembly.origin(), assembly.words(), &mut state);
+    state[Registers::PC] = options.entrypoint.unwrap_or(assembly.origin());
+    if options.report {
+        state.enable_profiling();
+    }
+
+    let keyboard = options.input.as_deref().map(AutomatedKeyboard::new);
+    let mut peripherals: Vec<&dyn Peripheral> = vec![&TerminalDisplay];
+    if let Some(keyboard) = &keyboard {
+        peripherals.push(keyboard);
+    }
+
+    match run_until(&mut state, &peripherals, &[], None, options.tick_cap) {
+        Ok(StopReason::Halted) => {}
+        Ok(StopReason::TickCap) => {
+            eprintln!("Still running after {} ticks; giving up", options.tick_cap);
+            return ExitCode::from(2);
+        }
+        Ok(StopReason::Breakpoint) | Ok(StopReason::Target) => {
+            unreachable!("no breakpoints or target address were set")
+        }
+        Err(error) => {
+            eprintln!("{:#}", error);
+            return ExitCode::from(2);
+        }
+    }
+
+    if options.report {
+        println!("\nBranch statistics:");
+        for row in format_branch_table(&state) {
+            println!("{}", row);
+        }
+    }
+    let mut failed = false;
+    for record in state.assertion_records() {
+        if !record.passed() {
+            eprintln!(
+                "Assertion failed at x{:04X}: expected x{:04X}, got x{:04X}",
+                record.address, record.expected, record.actual
+            );
+            failed = true;
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,557 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use tui::backend::{Backend, CrosstermBackend};
+use tui::layout::{Constraint, Direction, Layout};
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use tui::{Frame, Terminal};
+
+use assembler::output::{Color as AnsiColor, ColorChoice, Painter};
+use virtual_machine::parser::Instruction;
+use virtual_machine::peripherals::{BufferedDisplay, TerminalDisplay};
+use virtual_machine::repl::{
+    format_branch_table, format_memory_row, format_trap_table, parse_address, parse_command,
+    run_until, Cmd, ExprSet, MemDisplayMode, MessageLog, StopReason, SymbolTable,
+};
+use virtual_machine::state::{Registers, VmState};
+use virtual_machine::{load_object_file, load_words, run, tick};
+
+/// Safety cap for `run`/`continue` in the REPL so a runaway program cannot
+/// freeze the UI.
+const INTERACTIVE_TICK_CAP: u64 = 1_000_000;
+
+/// How many messages the REPL scrollback retains.
+const MESSAGE_CAPACITY: usize = 500;
+
+/// The bundled example program behind `--demo`, assembled at startup so it
+/// cannot rot out of sync with the assembler.
+const DEMO_SOURCE: &str = include_str!("../../../testcases/demo.asm");
+
+struct VmOptions {
+    program: Option<PathBuf>,
+    symbols: Option<PathBuf>,
+    entrypoint: Option<u16>,
+    interactive: bool,
+    report: bool,
+    demo: bool,
+    color: ColorChoice,
+}
+
+fn parse_options() -> VmOptions {
+    let mut options = VmOptions {
+        program: None,
+        symbols: None,
+        entrypoint: None,
+        interactive: false,
+        report: false,
+        demo: false,
+        color: ColorChoice::Auto,
+    };
+    let mut args = env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--interactive") | Some("-i") => options.interactive = true,
+            Some("--demo") => options.demo = true,
+            Some("--report") => options.report = true,
+            Some("--color") => {
+                let value = args.next().expect("--color requires a value (auto|always|never)");
+                options.color = ColorChoice::from_flag(&value.to_string_lossy())
+                    .expect("invalid color choice");
+            }
+            Some("--entrypoint") | Some("-e") => {
+                let value = args.next().expect("--entrypoint requires an address");
+                options.entrypoint = Some(
+                    parse_address(&value.to_string_lossy()).expect("invalid entrypoint address"),
+                );
+            }
+            Some("--symbols") => options.symbols = args.next().map(PathBuf::from),
+            Some("--program") | Some("-p") => options.program = args.next().map(PathBuf::from),
+            _ => options.program = Some(PathBuf::from(arg)),
+        }
+    }
+    options
+}
+
+struct ReplState {
+    messages: MessageLog,
+    input: String,
+    breakpoints: ExprSet,
+    watches: ExprSet,
+    /// Labels the breakpoint and watch expressions resolve against and the
+    /// assembly pane annotates. Filled by `--demo`, `--symbols` or
+    /// `load symbols`; plain object files carry no symbols.
+    symbols: SymbolTable,
+    mem_mode: MemDisplayMode,
+    cursor: Option<u16>,
+}
+
+impl ReplState {
+    fn new() -> Self {
+        Self {
+            messages: MessageLog::new(MESSAGE_CAPACITY),
+            input: String::new(),
+            breakpoints: ExprSet::new(),
+            watches: ExprSet::new(),
+            symbols: SymbolTable::new(),
+            mem_mode: MemDisplayMode::Words,
+            cursor: None,
+        }
+    }
+
+    fn push_message<S: Into<String>>(&mut self, message: S) {
+        self.messages.push(Style::default(), message);
+    }
+
+    fn push_error<S: Into<String>>(&mut self, message: S) {
+        self.messages
+            .push(Style::default().fg(Color::Red), message);
+    }
+}
+
+/// Executes a parsed command against the machine. Returns `true` when the
+/// REPL should exit.
+fn execute_command(
+    repl: &mut ReplState,
+    state: &mut VmState,
+    display: &BufferedDisplay,
+    cmd: Cmd,
+) -> bool {
+    match cmd {
+        Cmd::Quit => return true,
+        Cmd::Help => {
+            for line in [
+                "load <file>              load an object file and jump to its origin",
+                "load symbols <file>      load an lc3tools .sym symbol file",
+                "run | continue           run until halt or breakpoint",
+                "step [n]                 execute n instructions (default 1)",
+                "break <addr|symbol>      toggle a breakpoint",
+                "watch <addr|symbol>      toggle a watch expression",
+                "cursor <addr>            move the cursor in the assembly pane",
+                "until-cursor             run until the PC reaches the cursor",
+                "trap install <vec> <addr> point a trap vector at a handler",
+                "info traps               list installed trap vectors",
+                "info breaks              list breakpoints",
+                "info watches             show watched memory values",
+                "quit                     leave the REPL",
+            ] {
+                repl.push_message(line);
+            }
+        }
+        Cmd::Load(path) => match load_object_file(&path, state) {
+            Ok(origin) => {
+                state[Registers::PC] = origin;
+                state.resume();
+                repl.push_message(format!("Loaded \"{}\" at x{:04X}", path.display(), origin));
+                // Symbols may have moved; re-resolve every symbolic
+                // expression so nothing watches stale memory.
+                let symbols = repl.symbols.by_name().clone();
+                let mut failures = repl.breakpoints.reresolve(&symbols);
+                failures.extend(repl.watches.reresolve(&symbols));
+                for failure in failures {
+                    repl.push_error(failure);
+                }
+            }
+            Err(error) => repl.push_error(format!("{:#}", error)),
+        },
+        Cmd::LoadSymbols(path) => load_symbol_file(repl, &path),
+        Cmd::Step(count) => {
+            for _ in 0..count {
+                if !state.running() {
+                    repl.push_message("The program has halted");
+                    break;
+                }
+                if let Err(error) = tick(state, &[display]) {
+                    repl.push_error(format!("{:#}", error));
+                    break;
+                }
+            }
+            repl.push_message(format!("PC is now x{:04X}", state[Registers::PC]));
+        }
+        Cmd::Run => run_to(repl, state, display, None),
+        Cmd::Cursor(address) => {
+            repl.cursor = Some(address);
+            repl.push_message(format!("Cursor moved to x{:04X}", address));
+        }
+        Cmd::UntilCursor => match repl.cursor {
+            Some(target) => run_to(repl, state, display, Some(target)),
+            None => repl.push_error("No cursor is set; use 'cursor <addr>' first"),
+        },
+        Cmd::Break(expr) => {
+            let symbols = repl.symbols.by_name().clone();
+            match repl.breakpoints.toggle(&expr, &symbols) {
+                Ok(Some(address)) => {
+                    repl.push_message(format!("Set breakpoint at x{:04X}", address))
+                }
+                Ok(None) => repl.push_message(format!("Removed breakpoint '{}'", expr)),
+                Err(error) => repl.push_error(error),
+            }
+        }
+        Cmd::Watch(expr) => {
+            let symbols = repl.symbols.by_name().clone();
+            match repl.watches.toggle(&expr, &symbols) {
+                Ok(Some(address)) => repl.push_message(format!("Watching x{:04X}", address)),
+                Ok(None) => repl.push_message(format!("Removed watch '{}'", expr)),
+                Err(error) => repl.push_error(error),
+            }
+        }
+        Cmd::ViewMem(mode) => {
+            repl.mem_mode = mode;
+            repl.push_message(match mode {
+                MemDisplayMode::Words => "Memory pane now shows words",
+                MemDisplayMode::Bytes => "Memory pane now shows bytes",
+            });
+        }
+        Cmd::TrapInstall { vector, address } => {
+            state.install_trap(vector, address);
+            repl.push_message(format!("Trap x{:02X} now vectors to x{:04X}", vector, address));
+        }
+        Cmd::InfoTraps => {
+            for row in format_trap_table(state) {
+                repl.push_message(row);
+            }
+        }
+        Cmd::InfoBranches => {
+            for row in format_branch_table(state) {
+                repl.push_message(row);
+            }
+        }
+        Cmd::InfoBreaks => {
+            if repl.breakpoints.is_empty() {
+                repl.push_message("No breakpoints are set");
+            } else {
+                for entry in repl.breakpoints.entries().to_vec() {
+                    match entry.address() {
+                        Some(address) => repl.push_message(format!(
+                            "Breakpoint '{}' at x{:04X}",
+                            entry.text(),
+                            address
+                        )),
+                        None => repl
+                            .push_message(format!("Breakpoint '{}' (unresolved)", entry.text())),
+                    }
+                }
+            }
+        }
+        Cmd::InfoWatches => {
+            if repl.watches.is_empty() {
+                repl.push_message("No watches are set");
+            } else {
+                for entry in repl.watches.entries().to_vec() {
+                    match entry.address() {
+                        Some(address) => repl.push_message(format!(
+                            "{} = x{:04X}",
+                            entry.text(),
+                            state.memory()[address]
+                        )),
+                        None => {
+                            repl.push_message(format!("Watch '{}' (unresolved)", entry.text()))
+                        }
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Reads an lc3tools `.sym` file into the REPL's symbol table, confirming
+/// how many symbols arrived and re-resolving every symbolic expression so
+/// breakpoints and watches pick up the new addresses.
+fn load_symbol_file(repl: &mut ReplState, path: &Path) {
+    let table = fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|text| SymbolTable::from_lc3tools_sym(&text));
+    match table {
+        Ok(table) => {
+            repl.push_message(format!(
+                "Loaded {} symbols from \"{}\"",
+                table.len(),
+                path.display()
+            ));
+            repl.symbols = table;
+            let symbols = repl.symbols.by_name().clone();
+            let mut failures = repl.breakpoints.reresolve(&symbols);
+            failures.extend(repl.watches.reresolve(&symbols));
+            for failure in failures {
+                repl.push_error(failure);
+            }
+        }
+        Err(error) => repl.push_error(format!("\"{}\": {:#}", path.display(), error)),
+    }
+}
+
+fn run_to(
+    repl: &mut ReplState,
+    state: &mut VmState,
+    display: &BufferedDisplay,
+    target: Option<u16>,
+) {
+    if !state.running() {
+        repl.push_message("The program has halted; 'load' a program to restart");
+        return;
+    }
+    match run_until(
+        state,
+        &[display],
+        &repl.breakpoints.addresses(),
+        target,
+        INTERACTIVE_TICK_CAP,
+    ) {
+        Err(error) => repl.push_error(format!("{:#}", error)),
+        Ok(StopReason::Halted) => {
+            let message = match target {
+                Some(address) => format!(
+                    "Program halted at x{:04X} before reaching the cursor at x{:04X}",
+                    state[Registers::PC], address
+                ),
+                None => format!("Program halted at x{:04X}", state[Registers::PC]),
+            };
+            repl.push_message(message);
+        }
+        Ok(StopReason::Breakpoint) => {
+            repl.push_message(format!("Breakpoint hit at x{:04X}", state[Registers::PC]))
+        }
+        Ok(StopReason::Target) => {
+            repl.push_message(format!("Reached the cursor at x{:04X}", state[Registers::PC]))
+        }
+        Ok(StopReason::TickCap) => repl.push_message(format!(
+            "Still running after {} ticks; returning to the REPL",
+            INTERACTIVE_TICK_CAP
+        )),
+    }
+}
+
+/// Prints the outcome of every `.ASSERT` checkpoint the program hit, with
+/// the verdict colored when the output supports it.
+fn report_assertions(state: &VmState, painter: &Painter) {
+    if state.assertion_records().is_empty() {
+        return;
+    }
+    println!("\nAssertions:");
+    for record in state.assertion_records() {
+        let verdict = if record.passed() {
+            painter.paint(AnsiColor::Green, "pass")
+        } else {
+            painter.paint(AnsiColor::Red, "FAIL")
+        };
+        println!(
+            "x{:04X}  expected x{:04X}, got x{:04X} ({})",
+            record.address, record.expected, record.actual, verdict
+        );
+    }
+}
+
+fn create_registers_widget(state: &VmState) -> Paragraph<'static> {
+    let mut lines: Vec<Spans> = Vec::new();
+    for (index, value) in state.registers()[..8].iter().enumerate() {
+        lines.push(Spans::from(format!("R{}  x{:04X}", index, value)));
+    }
+    lines.push(Spans::from(format!("PC  x{:04X}", state[Registers::PC])));
+    lines.push(Spans::from(format!("PSR x{:04X}", state[Registers::PSR])));
+    Paragraph::new(lines).block(Block::default().title("Registers").borders(Borders::ALL))
+}
+
+fn create_assembly_widget(state: &VmState, cursor: Option<u16>, symbols: &SymbolTable) -> List<'static> {
+    let pc = state[Registers::PC];
+    let mut items = Vec::new();
+    for offset in -6i32..=6 {
+        let address = pc.wrapping_add(offset as u16);
+        let raw = state.memory()[address];
+        let marker = if offset == 0 { ">" } else { " " };
+        let label = symbols
+            .label_at(address)
+            .map(|name| format!("{}: ", name))
+            .unwrap_or_default();
+        let mut item = ListItem::new(format!(
+            "{} x{:04X}  {}{}",
+            marker,
+            address,
+            label,
+            Instruction::from_raw_strict(raw)
+        ));
+        if cursor == Some(address) {
+            item = item.style(Style::default().fg(Color::Yellow));
+        }
+        items.push(item);
+    }
+    List::new(items).block(Block::default().title("Assembly").borders(Borders::ALL))
+}
+
+fn create_memory_widget(state: &VmState, mode: MemDisplayMode) -> Paragraph<'static> {
+    let start = state[Registers::PC] & !0x7;
+    let mut lines: Vec<Spans> = Vec::new();
+    for row in 0..4u16 {
+        let address = start.wrapping_add(row * 8);
+        let words: Vec<u16> = (0..8)
+            .map(|offset| state.memory()[address.wrapping_add(offset)])
+            .collect();
+        lines.push(Spans::from(format_memory_row(address, &words, mode)));
+    }
+    Paragraph::new(lines).block(Block::default().title("Memory").borders(Borders::ALL))
+}
+
+fn create_messages_widget(repl: &ReplState, height: usize) -> List<'static> {
+    let items: Vec<ListItem> = repl
+        .messages
+        .entries()
+        .iter()
+        .rev()
+        .take(height)
+        .rev()
+        .map(|(style, text)| ListItem::new(Spans::from(Span::styled(text.clone(), *style))))
+        .collect();
+    List::new(items).block(Block::default().title("Messages").borders(Borders::ALL))
+}
+
+fn draw_ui<B: Backend>(frame: &mut Frame<B>, state: &VmState, repl: &ReplState) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(10),
+            Constraint::Length(6),
+            Constraint::Length(3),
+        ])
+        .split(frame.size());
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Length(13),
+            Constraint::Length(44),
+            Constraint::Min(20),
+        ])
+        .split(chunks[0]);
+
+    frame.render_widget(create_registers_widget(state), top[0]);
+    frame.render_widget(create_assembly_widget(state, repl.cursor, &repl.symbols), top[1]);
+    frame.render_widget(
+        create_messages_widget(repl, chunks[0].height.saturating_sub(2) as usize),
+        top[2],
+    );
+    frame.render_widget(create_memory_widget(state, repl.mem_mode), chunks[1]);
+    frame.render_widget(
+        Paragraph::new(format!("> {}", repl.input))
+            .block(Block::default().title("Command").borders(Borders::ALL)),
+        chunks[2],
+    );
+}
+
+/// Assembles and loads the bundled demo, fills the symbol table so its
+/// labels work in `break`/`watch` expressions, and queues a getting-started
+/// hint.
+fn load_demo(state: &mut VmState, repl: &mut ReplState) {
+    let assembly = assembler::assemble(DEMO_SOURCE).expect("the bundled demo must assemble");
+    load_words(assembly.origin(), assembly.words(), state);
+    state[Registers::PC] = assembly.origin();
+    for (name, location) in assembly.labels() {
+        repl.symbols.insert(name.clone(), location.address);
+    }
+    repl.push_message(format!("Loaded the echo demo at x{:04X}", assembly.origin()));
+    repl.push_message("Try: step, break WAIT, continue - typed keys are echoed back");
+}
+
+fn run_interactive(state: &mut VmState, options: &VmOptions) -> Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let result = interactive_loop(&mut terminal, state, options);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn interactive_loop<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    state: &mut VmState,
+    options: &VmOptions,
+) -> Result<()> {
+    let display = BufferedDisplay::new();
+    let mut repl = ReplState::new();
+    if options.demo {
+        load_demo(state, &mut repl);
+    } else if options.program.is_none() {
+        repl.push_error("No program loaded; use 'load <file>' to get started");
+    }
+    if let Some(path) = &options.symbols {
+        load_symbol_file(&mut repl, path);
+    }
+    repl.push_message("Type 'help' for a list of commands");
+
+    loop {
+        terminal.draw(|frame| draw_ui(frame, state, &repl))?;
+
+        if event::poll(Duration::from_millis(100))? {
+            if let Event::Key(key) = event::read()? {
+                match key.code {
+                    KeyCode::Char(character) => repl.input.push(character),
+                    KeyCode::Backspace => {
+                        repl.input.pop();
+                    }
+                    KeyCode::Esc => break,
+                    KeyCode::Enter => {
+                        let input = mem::take(&mut repl.input);
+                        match parse_command(&input) {
+                            Ok(cmd) => {
+                                if execute_command(&mut repl, state, &display, cmd) {
+                                    break;
+                                }
+                            }
+                            Err(error) => repl.push_error(error),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let output = display.take();
+        if !output.is_empty() {
+            repl.push_message(output);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let options = parse_options();
+
+    let mut state = VmState::new();
+    if let Some(path) = &options.program {
+        let origin = load_object_file(path, &mut state)?;
+        state[Registers::PC] = options.entrypoint.unwrap_or(origin);
+    }
+
+    if options.program.is_none() || options.interactive {
+        run_interactive(&mut state, &options)
+    } else {
+        if options.report {
+            state.enable_profiling();
+        }
+        run(&mut state, &[&TerminalDisplay])?;
+        report_assertions(&state, &Painter::new(options.color));
+        if options.report {
+            println!("\nBranch statistics:");
+            for row in format_branch_table(&state) {
+                println!("{}", row);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1228 @@
+//! An LC-3 virtual machine.
+//!
+//! Object files are loaded with [`load_object_file`] (or [`load_object`] /
+//! [`load_words`] for in-memory data) and executed with [`run`], which ticks
+//! the machine and its peripherals until the program halts.
+
+pub mod parser;
+pub mod peripherals;
+pub mod repl;
+pub mod state;
+pub mod util;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+
+use parser::Instruction;
+use peripherals::Peripheral;
+use state::{AssertionRecord, Registers, VmState, MEM_SIZE};
+use util::binary_add;
+
+/// Loads an object file (origin word followed by program words, all
+/// big-endian) and returns the origin. Files with a `.hex` extension are
+/// parsed as Intel HEX instead; the first segment's origin is returned.
+pub fn load_object_file(path: impl AsRef<Path>, state: &mut VmState) -> Result<u16> {
+    let path = path.as_ref();
+    if path.extension().and_then(|extension| extension.to_str()) == Some("hex") {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("could not read \"{}\"", path.display()))?;
+        let segments = assembler::formats::from_ihex(&text)
+            .map_err(|error| anyhow::anyhow!("\"{}\": {}", path.display(), error))?;
+        let origin = match segments.first() {
+            Some((origin, _)) => *origin,
+            None => bail!("\"{}\" contains no data records", path.display()),
+        };
+        for (segment_origin, words) in &segments {
+            check_segment_bounds(*segment_origin, words.len())?;
+            load_words(*segment_origin, words, state);
+        }
+        return Ok(origin);
+    }
+    let bytes =
+        fs::read(path).with_context(|| format!("could not read \"{}\"", path.display()))?;
+    load_object(&bytes, state)
+}
+
+pub fn load_object(bytes: &[u8], state: &mut VmState) -> Result<u16> {
+    load_object_with(bytes, state, ByteOrder::Big)
+}
+
+/// Word byte order of an object file. Files produced by this repo's
+/// assembler are big-endian unless told otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Big,
+    Little,
+}
+
+/// Like [`load_object`], but with an explicit word byte order, so files
+/// written with `lc3as --endian little` round-trip.
+pub fn load_object_with(bytes: &[u8], state: &mut VmState, byte_order: ByteOrder) -> Result<u16> {
+    if bytes.starts_with(assembler::formats::EXTENDED_OBJECT_MAGIC) {
+        // The extended format defines its own (big-endian) layout.
+        return load_extended_object(bytes, state).map(|image| image.origin);
+    }
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        bail!("Invalid object file length {}", bytes.len());
+    }
+    let words: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| match byte_order {
+            ByteOrder::Big => (pair[0] as u16) << 8 | pair[1] as u16,
+            ByteOrder::Little => (pair[1] as u16) << 8 | pair[0] as u16,
+        })
+        .collect();
+    let origin = words[0];
+    check_segment_bounds(origin, words.len() - 1)?;
+    load_words(origin, &words[1..], state);
+    Ok(origin)
+}
+
+/// Rejects a segment that would extend past the top of memory before it
+/// reaches [`load_words`], whose slice copy would panic on it. Object
+/// files are untrusted input; a malformed segment table must be a load
+/// error, not a crash.
+fn check_segment_bounds(origin: u16, length: usize) -> Result<()> {
+    if origin as usize + length > MEM_SIZE {
+        bail!(
+            "Segment at x{:04X} ({} words) extends past the top of memory",
+            origin,
+            length
+        );
+    }
+    Ok(())
+}
+
+/// An image loaded from an object file: the entry origin, every loaded
+/// `(origin, word count)` segment, and the symbol table embedded alongside
+/// the words (empty for the legacy format).
+#[derive(Debug)]
+pub struct LoadedImage {
+    pub origin: u16,
+    pub segments: Vec<(u16, usize)>,
+    pub symbols: HashMap<String, u16>,
+}
+
+/// Loads an extended object file (see `assembler::formats` for the
+/// layout): every segment is placed at its origin and the embedded symbol
+/// table is returned, so the REPL can show labels next to addresses.
+/// Legacy images (origin word followed by program words) are auto-detected
+/// and load with an empty symbol table.
+pub fn load_extended_object(bytes: &[u8], state: &mut VmState) -> Result<LoadedImage> {
+    if !bytes.starts_with(assembler::formats::EXTENDED_OBJECT_MAGIC) {
+        let origin = load_object(bytes, state)?;
+        return Ok(LoadedImage {
+            origin,
+            segments: vec![(origin, bytes.len() / 2 - 1)],
+            symbols: HashMap::new(),
+        });
+    }
+    let mut cursor = &bytes[assembler::formats::EXTENDED_OBJECT_MAGIC.len()..];
+    let version = take_word(&mut cursor)?;
+    if version != 1 {
+        bail!("Unsupported extended object version {}", version);
+    }
+    let segment_count = take_word(&mut cursor)?;
+    let mut table = Vec::with_capacity(segment_count as usize);
+    for _ in 0..segment_count {
+        let origin = take_word(&mut cursor)?;
+        let length = take_word(&mut cursor)?;
+        table.push((origin, length));
+    }
+    let Some((first_origin, _)) = table.first().copied() else {
+        bail!("Extended object file contains no segments");
+    };
+    let mut segments = Vec::with_capacity(table.len());
+    for (origin, length) in table {
+        check_segment_bounds(origin, length as usize)?;
+        let mut words = Vec::with_capacity(length as usize);
+        for _ in 0..length {
+            words.push(take_word(&mut cursor)?);
+        }
+        load_words(origin, &words, state);
+        segments.push((origin, length as usize));
+    }
+    let symbol_count = take_word(&mut cursor)?;
+    let mut symbols = HashMap::with_capacity(symbol_count as usize);
+    for _ in 0..symbol_count {
+        let address = take_word(&mut cursor)?;
+        let length = take_word(&mut cursor)? as usize;
+        if cursor.len() < length {
+            bail!("Truncated extended object file");
+        }
+        let (name, rest) = cursor.split_at(length);
+        cursor = rest;
+        let name = std::str::from_utf8(name)
+            .context("Symbol name is not valid UTF-8")?
+            .to_string();
+        symbols.insert(name, address);
+    }
+    Ok(LoadedImage {
+        origin: first_origin,
+        segments,
+        symbols,
+    })
+}
+
+/// Consumes one big-endian word from the front of `bytes`.
+fn take_word(bytes: &mut &[u8]) -> Result<u16> {
+    let Some((pair, rest)) = bytes.split_first_chunk::<2>() else {
+        bail!("Truncated extended object file");
+    };
+    *bytes = rest;
+    Ok(u16::from_be_bytes(*pair))
+}
+
+pub fn load_words(origin: u16, words: &[u16], state: &mut VmState) {
+    // `MEM_SIZE` covers the full 16-bit address space, so a segment that
+    // runs exactly up to xFFFF stays in bounds; only a segment that would
+    // wrap past the end of memory can make this slice panic, and the
+    // object loaders reject those via `check_segment_bounds` before
+    // calling here.
+    let start = origin as usize;
+    for (loaded_origin, loaded_length) in state.loaded_regions() {
+        let loaded_start = *loaded_origin as usize;
+        if start < loaded_start + *loaded_length as usize && loaded_start < start + words.len() {
+            warn!(
+                "Segment at x{:04X} ({} words) overlaps the segment loaded at x{:04X} ({} words)",
+                origin,
+                words.len(),
+                loaded_origin,
+                loaded_length
+            );
+        }
+    }
+    state.memory_mut().as_mut_slice()[start..start + words.len()].copy_from_slice(words);
+    state.record_loaded_region(origin, words.len() as u16);
+}
+
+/// Ticks the machine until the program halts.
+pub fn run(state: &mut VmState, peripherals: &[&dyn Peripheral]) -> Result<()> {
+    run_with_limit(state, peripherals, u64::MAX).map(|_| ())
+}
+
+/// Like [`run`], but stops with a distinct error once `max_ticks`
+/// instructions have executed without a halt, so harnesses can tell a
+/// runaway program from a genuine failure. Returns the number of ticks
+/// executed.
+pub fn run_with_limit(
+    state: &mut VmState,
+    peripherals: &[&dyn Peripheral],
+    max_ticks: u64,
+) -> Result<u64> {
+    let started = Instant::now();
+    let mut ticks: u64 = 0;
+    while state.running() {
+        if ticks >= max_ticks {
+            bail!(
+                "Tick limit of {} reached before the program halted",
+                max_ticks
+            );
+        }
+        tick(state, peripherals)?;
+        ticks += 1;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    if elapsed > 0.0 {
+        debug!(
+            "Executed {} instructions ({:.2} kHz)",
+            ticks,
+            ticks as f64 / elapsed / 1000.0
+        );
+    }
+    Ok(ticks)
+}
+
+/// Executes one instruction, then gives every peripheral a chance to run.
+/// Pending interrupts are accepted between instructions, before the fetch.
+pub fn tick(state: &mut VmState, peripherals: &[&dyn Peripheral]) -> Result<()> {
+    service_pending_interrupt(state);
+    execute_next_instruction(state)?;
+    state.record_tick();
+    for peripheral in peripherals {
+        peripheral.run(state);
+    }
+    Ok(())
+}
+
+/// Accepts the highest-priority pending interrupt, if one exceeds the
+/// running priority: pushes PSR and PC onto the supervisor stack, enters
+/// supervisor mode at the interrupt's priority and vectors through the
+/// interrupt table entry at x0100 + vector.
+fn service_pending_interrupt(state: &mut VmState) {
+    let Some((vector, priority)) = state.take_pending_interrupt() else {
+        return;
+    };
+    let psr = state[Registers::PSR];
+    let mut stack_pointer = state[Registers::R6];
+    stack_pointer = stack_pointer.wrapping_sub(1);
+    state.memory_mut()[stack_pointer] = psr;
+    stack_pointer = stack_pointer.wrapping_sub(1);
+    state.memory_mut()[stack_pointer] = state[Registers::PC];
+    state[Registers::R6] = stack_pointer;
+    state.set_psr((psr & 0x7FFF & !0x0700) | ((priority as u16 & 0b111) << 8));
+    state[Registers::PC] = state.memory()[0x0100u16.wrapping_add(vector)];
+}
+
+fn execute_next_instruction(state: &mut VmState) -> Result<()> {
+    let pc = state[Registers::PC];
+    let raw = state.memory()[pc];
+    let instruction = if state.strict_decode() {
+        Instruction::from_raw_strict(raw)
+    } else {
+        Instruction::from_raw(raw)
+    };
+    debug!("x{:04X}: {:?}", pc, instruction);
+    state.trace(pc, &instruction);
+
+    match instruction {
+        Instruction::AddImmediate { dr, sr1, imm5 } => {
+            let value = binary_add(state[sr1], imm5);
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::AddRegister { dr, sr1, sr2 } => {
+            let value = binary_add(state[sr1], state[sr2]);
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::AndImmediate { dr, sr1, imm5 } => {
+            let value = state[sr1] & imm5;
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::AndRegister { dr, sr1, sr2 } => {
+            let value = state[sr1] & state[sr2];
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::Br { n, z, p, pc_offset9 } => {
+            let (negative, zero, positive) = state.condition_codes();
+            let taken = n && negative || z && zero || p && positive;
+            if let Some(profile) = state.profile_mut() {
+                profile.record_branch(pc, taken);
+            }
+            if taken {
+                state[Registers::PC] = binary_add(pc, pc_offset9);
+            }
+        }
+        Instruction::Jmp { base_r } => {
+            // The `- 1` cancels the unconditional PC increment below; it
+            // must wrap so a jump to address 0 does not underflow.
+            state[Registers::PC] = state.registers()[base_r as usize].wrapping_sub(1);
+        }
+        Instruction::Jsr { pc_offset11 } => {
+            state[Registers::R7] = pc.wrapping_add(1);
+            state[Registers::PC] = binary_add(pc, pc_offset11);
+        }
+        Instruction::JsrRegister { base_r } => {
+            let target = state.registers()[base_r as usize];
+            state[Registers::R7] = pc.wrapping_add(1);
+            state[Registers::PC] = target.wrapping_sub(1);
+        }
+        Instruction::Ld { dr, pc_offset9 } => {
+            let address = binary_add(pc.wrapping_add(1), pc_offset9);
+            let value = state.memory_mut().read(address);
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::Ldi { dr, pc_offset9 } => {
+            let address = binary_add(pc.wrapping_add(1), pc_offset9);
+            let address = state.memory_mut().read(address);
+            let value = state.memory_mut().read(address);
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::Ldr { dr, base_r, offset6 } => {
+            let address = binary_add(state[base_r], offset6);
+            let value = state.memory_mut().read(address);
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::Lea { dr, pc_offset9 } => {
+            let value = binary_add(pc.wrapping_add(1), pc_offset9);
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::Not { dr, sr } => {
+            let value = !state[sr];
+            state[dr] = value;
+            state.update_condition_codes(value);
+        }
+        Instruction::Rti {} => {
+            if state[Registers::PSR] >> 15 == 1 {
+                // Privilege mode violation: push PSR and the return PC onto
+                // the supervisor stack, enter supervisor mode and vector
+                // through the exception table entry at x0100.
+                let psr = state[Registers::PSR];
+                let mut stack_pointer = state[Registers::R6];
+                stack_pointer = stack_pointer.wrapping_sub(1);
+                state.memory_mut()[stack_pointer] = psr;
+                stack_pointer = stack_pointer.wrapping_sub(1);
+                state.memory_mut()[stack_pointer] = pc.wrapping_add(1);
+                state[Registers::R6] = stack_pointer;
+                state.set_psr(psr & 0x7FFF);
+                // The -1 cancels the unconditional PC increment below.
+                state[Registers::PC] = state.memory()[0x0100].wrapping_sub(1);
+            } else {
+                let stack_pointer = state[Registers::R6];
+                state[Registers::PC] = state.memory()[stack_pointer].wrapping_sub(1);
+                let psr = state.memory()[binary_add(stack_pointer, 1)];
+                state.set_psr(psr);
+                state[Registers::R6] = binary_add(stack_pointer, 2);
+            }
+        }
+        Instruction::St { sr, pc_offset9 } => {
+            let address = binary_add(pc.wrapping_add(1), pc_offset9);
+            let value = state[sr];
+            state.memory_mut().write(address, value);
+        }
+        Instruction::Sti { sr, pc_offset9 } => {
+            let address = binary_add(pc.wrapping_add(1), pc_offset9);
+            let address = state.memory_mut().read(address);
+            let value = state[sr];
+            state.memory_mut().write(address, value);
+        }
+        Instruction::Str { sr, base_r, offset6 } => {
+            let address = binary_add(state[base_r], offset6);
+            let value = state[sr];
+            state.memory_mut().write(address, value);
+        }
+        Instruction::Reserved {} => {
+            bail!("Illegal opcode x{:04X} at x{:04X}: the 0b1101 opcode is reserved", raw, pc);
+        }
+        Instruction::Malformed { raw, reason } => {
+            bail!("Malformed instruction x{:04X} at x{:04X}: {}", raw, pc, reason);
+        }
+        Instruction::Trap { trapvect8 } => {
+            if trapvect8 == 0x25 {
+                // HALT works even without an OS image loaded.
+                state.halt();
+            } else if trapvect8 == 0x7F {
+                // An `.ASSERT` checkpoint: the assembler lowers it to
+                // TRAP x7F followed by a descriptor word (bit 15 set for a
+                // memory operand, register number otherwise), the memory
+                // address and the expected value.
+                let descriptor = state.memory()[pc.wrapping_add(1)];
+                let operand = state.memory()[pc.wrapping_add(2)];
+                let expected = state.memory()[pc.wrapping_add(3)];
+                let actual = if descriptor >> 15 == 1 {
+                    state.memory()[operand]
+                } else {
+                    state.registers()[(descriptor & 0x7) as usize]
+                };
+                state.record_assertion(AssertionRecord {
+                    address: pc,
+                    expected,
+                    actual,
+                });
+                state[Registers::PC] = pc.wrapping_add(3);
+            } else if state.builtin_traps() && (0x20..=0x24).contains(&trapvect8) {
+                execute_builtin_trap(state, trapvect8, pc);
+            } else {
+                state[Registers::R7] = pc.wrapping_add(1);
+                state[Registers::PC] = state.memory()[trapvect8].wrapping_sub(1);
+            }
+        }
+    }
+
+    state[Registers::PC] = binary_add(state[Registers::PC], 1);
+    Ok(())
+}
+
+/// Services TRAP x20..x24 directly against the memory-mapped devices when
+/// built-in traps are enabled (HALT is handled earlier). Routines that
+/// span several ticks — waiting for a key, or printing one character per
+/// tick so the display peripheral can drain the data register in between —
+/// leave the PC on the trap instruction and pick up where they left off on
+/// the next tick.
+fn execute_builtin_trap(state: &mut VmState, trapvect8: u16, pc: u16) {
+    // The -1 cancels the unconditional PC increment, re-executing the trap.
+    let retry = |state: &mut VmState| state[Registers::PC] = pc.wrapping_sub(1);
+    match trapvect8 {
+        // GETC: wait for a key, no echo.
+        0x20 => match read_key(state) {
+            Some(character) => state[Registers::R0] = character,
+            None => retry(state),
+        },
+        // OUT: wait for the display, then write R0's low byte.
+        0x21 => {
+            let character = state[Registers::R0] & 0xFF;
+            if !write_char(state, character) {
+                retry(state);
+            }
+        }
+        // PUTS: the zero-terminated string R0 points at, one word per
+        // character.
+        0x22 => {
+            let address = state[Registers::R0].wrapping_add(state.trap_progress());
+            let character = state.memory()[address] & 0xFF;
+            if character == 0 {
+                state.reset_trap_progress();
+            } else {
+                if write_char(state, character) {
+                    state.advance_trap_progress();
+                }
+                retry(state);
+            }
+        }
+        // IN: GETC followed by an echo of the character (the OS prompt is
+        // not reproduced).
+        0x23 => {
+            if state.trap_progress() == 0 {
+                if let Some(character) = read_key(state) {
+                    state[Registers::R0] = character;
+                    state.advance_trap_progress();
+                }
+                retry(state);
+            } else {
+                let character = state[Registers::R0] & 0xFF;
+                if write_char(state, character) {
+                    state.reset_trap_progress();
+                } else {
+                    retry(state);
+                }
+            }
+        }
+        // PUTSP: the string packed two characters per word, low byte first.
+        0x24 => {
+            let progress = state.trap_progress();
+            let address = state[Registers::R0].wrapping_add(progress / 2);
+            let character = (state.memory()[address] >> (8 * (progress % 2))) & 0xFF;
+            if character == 0 {
+                state.reset_trap_progress();
+            } else {
+                if write_char(state, character) {
+                    state.advance_trap_progress();
+                }
+                retry(state);
+            }
+        }
+        _ => unreachable!("only x20..x24 are dispatched here"),
+    }
+}
+
+/// Writes `character` to the display data register if it is free; the
+/// display peripheral drains it after the tick.
+fn write_char(state: &mut VmState, character: u16) -> bool {
+    if state.memory()[peripherals::DDR] != 0 {
+        return false;
+    }
+    state.memory_mut()[peripherals::DDR] = character;
+    true
+}
+
+/// Consumes a pending key from the keyboard registers, if one is ready.
+fn read_key(state: &mut VmState) -> Option<u16> {
+    if state.memory()[peripherals::KBSR] >> 15 != 1 {
+        return None;
+    }
+    let character = state.memory()[peripherals::KBDR] & 0xFF;
+    state.memory_mut()[peripherals::KBSR] = 0;
+    Some(character)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_and_run(words: &[u16]) -> VmState {
+        let mut state = VmState::new();
+        load_words(0x3000, words, &mut state);
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+        state
+    }
+
+    #[test]
+    fn test_the_bundled_demo_reaches_its_input_prompt() {
+        let source = include_str!("../../testcases/demo.asm");
+        let assembly = assembler::assemble(source).unwrap();
+        let mut state = VmState::new();
+        load_words(assembly.origin(), assembly.words(), &mut state);
+        state[Registers::PC] = assembly.origin();
+
+        let display = peripherals::BufferedDisplay::new();
+        // The automated keyboard assumes a key is consumed after a fixed
+        // cooldown, so keys offered while the prompt is still printing
+        // expire unseen; feed enough to outlast it.
+        let keyboard = peripherals::AutomatedKeyboard::new(&"hi".repeat(10));
+        // The demo never halts; the cap just bounds the test.
+        repl::run_until(&mut state, &[&display, &keyboard], &[], None, 100_000).unwrap();
+        let output = display.take();
+        assert!(output.contains("> "), "no prompt in output: {:?}", output);
+        assert!(output.contains("hi"), "keys were not echoed: {:?}", output);
+    }
+
+    #[test]
+    fn test_the_top_memory_word_is_addressable() {
+        let mut state = VmState::new();
+        // Loading a segment that ends exactly at xFFFF must not slice past
+        // the end of memory.
+        load_words(0xFFFF, &[0xBEEF], &mut state);
+        assert_eq!(state.memory()[0xFFFF], 0xBEEF);
+
+        // Storing to and loading from xFFFF through a base register.
+        let state = load_and_run(&[
+            0x5020, // AND R0, R0, #0
+            0x103F, // ADD R0, R0, #-1   ; R0 = xFFFF
+            0x5260, // AND R1, R1, #0
+            0x1265, // ADD R1, R1, #5
+            0x7200, // STR R1, R0, #0
+            0x6400, // LDR R2, R0, #0
+            0xF025, // HALT
+        ]);
+        assert_eq!(state.memory()[0xFFFF], 5);
+        assert_eq!(state[Registers::R2], 5);
+    }
+
+    #[test]
+    fn test_the_state_counts_executed_instructions() {
+        let mut state = load_and_run(&[0x1021, 0x1021, 0xF025]); // ADD, ADD, HALT
+        assert_eq!(state.ticks(), 3);
+        state.reset_ticks();
+        assert_eq!(state.ticks(), 0);
+    }
+
+    #[test]
+    fn test_run_with_limit_stops_runaway_programs() {
+        // BRnzp to itself never halts.
+        let mut state = VmState::new();
+        load_words(0x3000, &[0x0FFF], &mut state);
+        state[Registers::PC] = 0x3000;
+        let error = run_with_limit(&mut state, &[], 1_000).unwrap_err();
+        assert!(
+            error.to_string().contains("Tick limit"),
+            "unexpected message: {}",
+            error
+        );
+
+        // A halting program reports how many ticks it took.
+        let mut state = VmState::new();
+        load_words(0x3000, &[0x1021, 0xF025], &mut state); // ADD R0, R0, #1; HALT
+        state[Registers::PC] = 0x3000;
+        assert_eq!(run_with_limit(&mut state, &[], 1_000).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_execution_at_the_top_of_memory_wraps_the_pc() {
+        let mut state = VmState::new();
+        // An LD at xFFFF reads PC-relative to x0000, as the hardware would.
+        load_words(0xFFFF, &[0x2004], &mut state); // LD R0, x0004
+        load_words(0x0000, &[0xF025], &mut state); // HALT
+        load_words(0x0004, &[0xABCD], &mut state);
+        state[Registers::PC] = 0xFFFF;
+        run(&mut state, &[]).unwrap();
+        assert_eq!(state[Registers::R0], 0xABCD);
+        assert!(!state.running());
+    }
+
+    #[test]
+    fn test_jmp_to_address_zero_wraps_instead_of_underflowing() {
+        let mut state = VmState::new();
+        load_words(0x0000, &[0xF025], &mut state); // HALT
+        load_words(0x3000, &[0x5020, 0xC000], &mut state); // AND R0, R0, #0; JMP R0
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+        assert!(!state.running());
+
+        // JSRR through a zeroed base register takes the same path.
+        let mut state = VmState::new();
+        load_words(0x0000, &[0xF025], &mut state);
+        load_words(0x3000, &[0x5020, 0x4000], &mut state); // AND R0, R0, #0; JSRR R0
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+        assert!(!state.running());
+        assert_eq!(state[Registers::R7], 0x3002);
+    }
+
+    #[test]
+    fn test_reserved_opcode_is_a_recoverable_error() {
+        let mut state = VmState::new();
+        load_words(0x3000, &[0xD000], &mut state);
+        state[Registers::PC] = 0x3000;
+        let error = run(&mut state, &[]).unwrap_err();
+        assert!(
+            error.to_string().contains("reserved"),
+            "unexpected message: {}",
+            error
+        );
+        // The error is recoverable: the PC still points at the offending
+        // word and the state can keep running.
+        assert_eq!(state[Registers::PC], 0x3000);
+    }
+
+    #[test]
+    fn test_load_object_honors_the_byte_order() {
+        let mut state = VmState::new();
+        load_object_with(&[0x30, 0x00, 0x12, 0x34], &mut state, ByteOrder::Big).unwrap();
+        assert_eq!(state.memory()[0x3000], 0x1234);
+
+        let mut state = VmState::new();
+        load_object_with(&[0x00, 0x30, 0x34, 0x12], &mut state, ByteOrder::Little).unwrap();
+        assert_eq!(state.memory()[0x3000], 0x1234);
+    }
+
+    #[test]
+    fn test_load_object_file_reads_intel_hex() {
+        let path = std::env::temp_dir().join("lc3vm_test_load.hex");
+        // One word (0x1234) at byte address 0x6000, i.e. word address 0x3000.
+        fs::write(&path, ":02600000123458\n:00000001FF\n").unwrap();
+        let mut state = VmState::new();
+        let origin = load_object_file(&path, &mut state).unwrap();
+        fs::remove_file(&path).ok();
+        assert_eq!(origin, 0x3000);
+        assert_eq!(state.memory()[0x3000], 0x1234);
+    }
+
+    /// Test support for the randomized pipeline test below: generates
+    /// random but well-formed programs that are guaranteed to terminate.
+    mod program_gen {
+        /// A tiny xorshift PRNG so the test is deterministic per seed.
+        pub struct Rng(u64);
+
+        impl Rng {
+            pub fn new(seed: u64) -> Self {
+                Rng(seed.wrapping_mul(2685821657736338717).max(1))
+            }
+
+            fn next(&mut self) -> u64 {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            }
+
+            pub fn range(&mut self, bound: u64) -> u64 {
+                self.next() % bound
+            }
+        }
+
+        /// One straight-line instruction over R0-R4 (R5 is the loop
+        /// counter, R6 the scratch base pointer).
+        fn arithmetic(rng: &mut Rng) -> String {
+            let dst = rng.range(5);
+            let a = rng.range(5);
+            match rng.range(5) {
+                0 => format!("ADD R{}, R{}, #{}", dst, a, rng.range(31) as i64 - 15),
+                1 => format!("ADD R{}, R{}, R{}", dst, a, rng.range(5)),
+                2 => format!("AND R{}, R{}, R{}", dst, a, rng.range(5)),
+                3 => format!("NOT R{}, R{}", dst, a),
+                _ => match rng.range(2) {
+                    0 => format!("STR R{}, R6, #{}", dst, rng.range(32)),
+                    _ => format!("LDR R{}, R{}, #{}", dst, 6, rng.range(32)),
+                },
+            }
+        }
+
+        /// A generation unit: either one instruction or a whole bounded
+        /// loop. Shrinking removes whole units so loops stay paired.
+        pub fn units(seed: u64) -> Vec<Vec<String>> {
+            let mut rng = Rng::new(seed);
+            let mut units = Vec::new();
+            let mut next_loop = 0;
+            for _ in 0..rng.range(25) + 3 {
+                if rng.range(5) == 0 {
+                    let label = format!("LOOP{}", next_loop);
+                    next_loop += 1;
+                    let mut block = vec![
+                        "AND R5, R5, #0".to_string(),
+                        format!("ADD R5, R5, #{}", rng.range(7) + 1),
+                    ];
+                    let body = rng.range(4) + 1;
+                    block.push(format!("{} ADD R0, R0, #1", label));
+                    for _ in 0..body {
+                        block.push(arithmetic(&mut rng));
+                    }
+                    block.push("ADD R5, R5, #-1".to_string());
+                    block.push(format!("BRp {}", label));
+                    units.push(block);
+                } else {
+                    units.push(vec![arithmetic(&mut rng)]);
+                }
+            }
+            units
+        }
+
+        pub fn render(units: &[Vec<String>]) -> String {
+            let mut lines = vec![".ORIG x3000".to_string(), "LEA R6, SCRATCH".to_string()];
+            for unit in units {
+                lines.extend(unit.iter().cloned());
+            }
+            lines.push("HALT".to_string());
+            lines.push("SCRATCH .BLKW #32".to_string());
+            lines.push(".END".to_string());
+            lines.join("\n") + "\n"
+        }
+    }
+
+    /// Assembles `source`, runs it twice (once from the in-memory words,
+    /// once after a byte round-trip through the object format) and checks
+    /// both executions leave identical machine state.
+    fn pipeline_is_consistent(source: &str) -> bool {
+        let assembly = match assembler::assemble(source) {
+            Ok(assembly) => assembly,
+            Err(_) => return false,
+        };
+        let data = assembly.data();
+
+        let run_words = |state: &mut VmState| {
+            state[Registers::PC] = 0x3000;
+            // Generated programs always terminate; the cap only guards
+            // against VM bugs.
+            matches!(
+                repl::run_until(state, &[], &[], None, 500_000),
+                Ok(repl::StopReason::Halted)
+            )
+        };
+
+        let mut direct = VmState::new();
+        load_words(data[0], &data[1..], &mut direct);
+        if !run_words(&mut direct) {
+            return false;
+        }
+
+        let bytes: Vec<u8> = data.iter().flat_map(|word| word.to_be_bytes()).collect();
+        let mut reloaded = VmState::new();
+        if load_object(&bytes, &mut reloaded).is_err() {
+            return false;
+        }
+        if !run_words(&mut reloaded) {
+            return false;
+        }
+
+        direct.registers()[..8] == reloaded.registers()[..8]
+            && direct.memory().as_slice() == reloaded.memory().as_slice()
+    }
+
+    /// Stress test for the whole source -> words -> bytes -> load -> run
+    /// pipeline. Slow, so it only runs with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_random_programs_survive_an_object_file_roundtrip() {
+        for seed in 0..200 {
+            let mut units = program_gen::units(seed);
+            if pipeline_is_consistent(&program_gen::render(&units)) {
+                continue;
+            }
+            // Shrink: drop units while the failure reproduces, so the
+            // reported program is minimal.
+            let mut index = 0;
+            while index < units.len() {
+                let candidate: Vec<_> = units
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != index)
+                    .map(|(_, unit)| unit.clone())
+                    .collect();
+                if !pipeline_is_consistent(&program_gen::render(&candidate)) {
+                    units = candidate;
+                } else {
+                    index += 1;
+                }
+            }
+            panic!(
+                "seed {} produced an inconsistent program:\n{}",
+                seed,
+                program_gen::render(&units)
+            );
+        }
+    }
+
+    #[test]
+    fn test_missing_object_file_error_quotes_the_path() {
+        let mut state = VmState::new();
+        let error = load_object_file("no such file.obj", &mut state).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("could not read \"no such file.obj\""),
+            "unexpected error: {:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_strict_decode_refuses_malformed_words() {
+        let mut state = VmState::new();
+        load_words(0x3000, &[0x903E, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        state.set_strict_decode(true);
+        let error = run(&mut state, &[]).unwrap_err();
+        assert!(error.to_string().contains("Malformed instruction x903E at x3000"));
+
+        // The same program runs through under lenient decoding.
+        let mut state = VmState::new();
+        load_words(0x3000, &[0x903E, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+    }
+
+    #[test]
+    fn test_add_immediate_program() {
+        // ADD R0, R0, #5 / ADD R0, R0, #10 / TRAP x25
+        let state = load_and_run(&[0x1025, 0x102A, 0xF025]);
+        assert_eq!(state[Registers::R0], 15);
+        assert!(!state.running());
+    }
+
+    #[test]
+    fn test_branch_loop() {
+        // AND R0, R0, #0 / ADD R0, R0, #5 / ADD R0, R0, #-1 / BRp -2 / TRAP x25
+        let state = load_and_run(&[0x5020, 0x1025, 0x103F, 0x03FE, 0xF025]);
+        assert_eq!(state[Registers::R0], 0);
+    }
+
+    #[test]
+    fn test_condition_codes_track_last_result() {
+        // ADD R0, R0, #-1 / TRAP x25
+        let state = load_and_run(&[0x103F, 0xF025]);
+        assert_eq!(state[Registers::PSR] & 0b111, 0b100);
+    }
+
+    #[test]
+    fn test_rti_in_user_mode_takes_the_privilege_exception() {
+        let mut state = VmState::new();
+        load_words(0x3000, &[0x8000], &mut state); // RTI
+        // Handler: AND R0, R0, #0 / ADD R0, R0, #10 / TRAP x25
+        load_words(0x0200, &[0x5020, 0x102A, 0xF025], &mut state);
+        state.memory_mut()[0x0100] = 0x0200;
+        state[Registers::R6] = 0x2000;
+        state[Registers::PC] = 0x3000;
+        state.set_psr(0x8002); // user mode
+        run(&mut state, &[]).unwrap();
+        // The handler ran in supervisor mode, with the old PSR and the
+        // return address pushed onto the supervisor stack.
+        assert_eq!(state[Registers::R0], 10);
+        assert_eq!(state[Registers::R6], 0x1FFE);
+        assert_eq!(state.memory()[0x1FFF], 0x8002);
+        assert_eq!(state.memory()[0x1FFE], 0x3001);
+        assert_eq!(state[Registers::PSR] >> 15, 0);
+    }
+
+    #[test]
+    fn test_supervisor_rti_over_zeroed_memory_wraps_instead_of_panicking() {
+        let mut state = VmState::new();
+        load_words(0x0000, &[0xF025], &mut state); // HALT
+        load_words(0x3000, &[0x8000], &mut state); // RTI
+        // A mis-set stack pointer over zeroed memory pops a return PC of
+        // zero; that must wrap like every other PC arithmetic, not panic.
+        state.set_psr(0x0002); // supervisor mode
+        state[Registers::R6] = 0x2000;
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+        assert!(!state.running());
+    }
+
+    #[test]
+    fn test_extended_object_round_trips_symbols() {
+        let source = include_str!("../../testcases/demo.asm");
+        let assembly = assembler::assemble(source).unwrap();
+        let mut bytes = Vec::new();
+        assembly.write_extended_object(&mut bytes).unwrap();
+
+        let mut state = VmState::new();
+        let image = load_extended_object(&bytes, &mut state).unwrap();
+        assert_eq!(image.origin, assembly.origin());
+        for (name, address) in assembly.symbols() {
+            assert_eq!(image.symbols.get(name), Some(&address), "{}", name);
+        }
+        // The words landed in memory exactly like a legacy load would
+        // place them.
+        let start = assembly.origin() as usize;
+        assert_eq!(
+            &state.memory().as_slice()[start..start + assembly.words().len()],
+            assembly.words()
+        );
+    }
+
+    #[test]
+    fn test_two_segment_objects_load_both_regions() {
+        let words: &[u16] = &[
+            1, // version
+            2, // segment count
+            0x3000, 2, // segment table
+            0x4000, 1,
+            0x1021, 0xF025, // first segment
+            0xBEEF, // second segment
+            0, // symbol count
+        ];
+        let mut bytes = assembler::formats::EXTENDED_OBJECT_MAGIC.to_vec();
+        for word in words {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        let mut state = VmState::new();
+        let image = load_extended_object(&bytes, &mut state).unwrap();
+        assert_eq!(image.origin, 0x3000);
+        assert_eq!(image.segments, vec![(0x3000, 2), (0x4000, 1)]);
+        assert_eq!(state.memory()[0x3000], 0x1021);
+        assert_eq!(state.memory()[0x3001], 0xF025);
+        assert_eq!(state.memory()[0x4000], 0xBEEF);
+    }
+
+    #[test]
+    fn test_segments_past_the_top_of_memory_are_a_load_error() {
+        // Origin xFFFF followed by two data words: only xFFFF exists.
+        let bytes = [0xFF, 0xFF, 0x12, 0x34, 0x56, 0x78];
+        let mut state = VmState::new();
+        let error = load_object(&bytes, &mut state).unwrap_err();
+        assert!(
+            error.to_string().contains("extends past the top of memory"),
+            "{}",
+            error
+        );
+
+        // The same check guards the extended format's segment table.
+        let words: &[u16] = &[
+            1, // version
+            1, // segment count
+            0xFFFF, 2, // segment table
+            0x1234, 0x5678, // segment words
+            0, // symbol count
+        ];
+        let mut bytes = assembler::formats::EXTENDED_OBJECT_MAGIC.to_vec();
+        for word in words {
+            bytes.extend_from_slice(&word.to_be_bytes());
+        }
+        let error = load_extended_object(&bytes, &mut state).unwrap_err();
+        assert!(
+            error.to_string().contains("extends past the top of memory"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_legacy_objects_load_through_the_extended_loader() {
+        // Origin x3000 followed by a single HALT, big-endian.
+        let bytes = [0x30, 0x00, 0xF0, 0x25];
+        let mut state = VmState::new();
+        let image = load_extended_object(&bytes, &mut state).unwrap();
+        assert_eq!(image.origin, 0x3000);
+        assert!(image.symbols.is_empty());
+        assert_eq!(state.memory()[0x3000], 0xF025);
+    }
+
+    #[test]
+    fn test_rti() {
+        let mut state = VmState::new();
+        // Main program: AND R0, R0, #0 / ADD R0, R0, #1 / TRAP x25
+        load_words(0x3000, &[0x5020, 0x1021, 0xF025], &mut state);
+        // Handler: AND R1, R1, #0 / ADD R1, R1, #5 / RTI
+        load_words(0x0200, &[0x5260, 0x1265, 0x8000], &mut state);
+        state.memory_mut()[0x0180] = 0x0200; // keyboard vector x80
+        state[Registers::R6] = 0x2000;
+        state[Registers::PC] = 0x3000;
+        state.raise_interrupt(0x80, 4);
+        run(&mut state, &[]).unwrap();
+        // The handler ran at priority 4 and RTI restored the interrupted
+        // context, so the main program still completed.
+        assert_eq!(state[Registers::R1], 5);
+        assert_eq!(state[Registers::R0], 1);
+        assert_eq!(state[Registers::R6], 0x2000);
+        assert_eq!(state[Registers::PSR] >> 15, 1); // back in user mode
+    }
+
+    #[test]
+    fn test_interrupts_below_the_running_priority_stay_pending() {
+        let mut state = VmState::new();
+        // ADD R0, R0, #1 / TRAP x25
+        load_words(0x3000, &[0x1021, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        state.set_psr(state[Registers::PSR] | 0x0400); // running at priority 4
+        state.raise_interrupt(0x80, 4); // equal priority does not preempt
+        state.raise_interrupt(0x80, 3);
+        run(&mut state, &[]).unwrap();
+        // Neither interrupt was accepted: R6 was never touched and the
+        // program ran straight through to its HALT.
+        assert_eq!(state[Registers::R6], 0);
+        assert_eq!(state[Registers::R0], 1);
+        assert_eq!(state.priority(), 4);
+    }
+
+    #[test]
+    fn test_profiler_counts_taken_and_not_taken_branches() {
+        let mut state = VmState::new();
+        // AND R0, R0, #0 / ADD R0, R0, #5 / ADD R0, R0, #-1 / BRp -2 / TRAP x25
+        load_words(0x3000, &[0x5020, 0x1025, 0x103F, 0x03FE, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        state.enable_profiling();
+        run(&mut state, &[]).unwrap();
+        let branches = state.profile().unwrap().branches();
+        let stats = branches[&0x3003];
+        // The loop decrements R0 from 5 to 0: four taken iterations, then
+        // one fall-through.
+        assert_eq!(stats.evaluated, 5);
+        assert_eq!(stats.taken, 4);
+        assert_eq!(branches.len(), 1);
+    }
+
+    #[test]
+    fn test_profiling_is_off_by_default() {
+        let state = load_and_run(&[0x03FF, 0xF025]);
+        assert!(state.profile().is_none());
+    }
+
+    #[test]
+    fn test_assert_checkpoints_record_expected_and_actual_values() {
+        // Assembled from a program with one passing and one failing
+        // `.ASSERT` around an off-by-one increment.
+        let source = "\
+.ORIG x3000
+AND R0, R0, #0
+ADD R0, R0, #5
+.ASSERT R0, #5, \"R0 should be five\"
+ADD R0, R0, #1
+.ASSERT R0, #7, \"off by one\"
+TRAP x25
+.END
+";
+        let assembly = assembler::assemble(source).unwrap();
+        let data = assembly.data();
+        let mut state = VmState::new();
+        load_words(data[0], &data[1..], &mut state);
+        state[Registers::PC] = data[0];
+        run(&mut state, &[]).unwrap();
+
+        let records = state.assertion_records();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].passed());
+        assert_eq!((records[0].expected, records[0].actual), (5, 5));
+        assert!(!records[1].passed());
+        assert_eq!((records[1].expected, records[1].actual), (7, 6));
+        assert_eq!(assembly.assertions()[&records[1].address], "off by one");
+    }
+
+    #[test]
+    fn test_builtin_puts_prints_without_an_os_image() {
+        let mut state = VmState::new();
+        state.use_builtin_traps(true);
+        // LEA R0, #2 / PUTS / TRAP x25 / "hi"
+        load_words(
+            0x3000,
+            &[0xE002, 0xF022, 0xF025, 'h' as u16, 'i' as u16, 0],
+            &mut state,
+        );
+        state[Registers::PC] = 0x3000;
+        let display = peripherals::BufferedDisplay::new();
+        run(&mut state, &[&display]).unwrap();
+        assert_eq!(display.take(), "hi");
+    }
+
+    #[test]
+    fn test_builtin_out_prints_r0() {
+        let mut state = VmState::new();
+        state.use_builtin_traps(true);
+        // LD R0, #2 / OUT / TRAP x25 / x0041
+        load_words(0x3000, &[0x2002, 0xF021, 0xF025, 0x0041], &mut state);
+        state[Registers::PC] = 0x3000;
+        let display = peripherals::BufferedDisplay::new();
+        run(&mut state, &[&display]).unwrap();
+        assert_eq!(display.take(), "A");
+    }
+
+    #[test]
+    fn test_on_read_hooks_fire_the_moment_a_register_is_read() {
+        /// Clears KBSR the instant KBDR is read, the way real hardware
+        /// does, instead of approximating it with a tick counter.
+        struct ClearOnRead;
+        impl Peripheral for ClearOnRead {
+            fn on_read(&self, address: u16, memory: &mut state::VmMemory) {
+                if address == peripherals::KBDR {
+                    memory[peripherals::KBSR] = 0;
+                }
+            }
+        }
+        let mut state = VmState::new();
+        state.memory_mut().register_hook(std::rc::Rc::new(ClearOnRead));
+        // LDI R0, KBDR / LDI R1, KBSR / TRAP x25, with the device
+        // addresses in the trailing words.
+        load_words(
+            0x3000,
+            &[0xA002, 0xA202, 0xF025, peripherals::KBDR, peripherals::KBSR],
+            &mut state,
+        );
+        // Setting up through plain indexing does not trip the hook.
+        state.memory_mut()[peripherals::KBSR] = 0x8000;
+        state.memory_mut()[peripherals::KBDR] = 'A' as u16;
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+        // The first LDI consumed the key; the very next instruction
+        // already sees the ready bit cleared, with no tick in between.
+        assert_eq!(state[Registers::R0], 'A' as u16);
+        assert_eq!(state[Registers::R1], 0);
+    }
+
+    #[test]
+    fn test_on_write_hooks_see_the_value_in_the_same_instruction() {
+        /// Captures and drains DDR the instant it is written, with no
+        /// per-tick polling involved.
+        #[derive(Default)]
+        struct InstantDisplay {
+            output: std::cell::RefCell<String>,
+        }
+        impl Peripheral for InstantDisplay {
+            fn on_write(&self, address: u16, memory: &mut state::VmMemory) {
+                let character = memory[peripherals::DDR];
+                if address == peripherals::DDR && character != 0 {
+                    self.output.borrow_mut().push((character as u8) as char);
+                    memory[peripherals::DDR] = 0;
+                }
+            }
+        }
+        let display = std::rc::Rc::new(InstantDisplay::default());
+        let mut state = VmState::new();
+        state.memory_mut().register_hook(display.clone());
+        // LD R0, #2 / STI R0, #2 / TRAP x25 / x0041 / DDR
+        load_words(
+            0x3000,
+            &[0x2002, 0xB002, 0xF025, 0x0041, peripherals::DDR],
+            &mut state,
+        );
+        state[Registers::PC] = 0x3000;
+        // No peripherals are polled: the hook alone drains the register.
+        run(&mut state, &[]).unwrap();
+        assert_eq!(display.output.borrow().as_str(), "A");
+        assert_eq!(state.memory()[peripherals::DDR], 0);
+    }
+
+    #[test]
+    fn test_trace_hook_sees_every_executed_instruction() {
+        let mut state = VmState::new();
+        // AND R0, R0, #0 / ADD R0, R0, #2 / ADD R0, R0, #-1 / BRp -2 / TRAP x25
+        load_words(0x3000, &[0x5020, 0x1022, 0x103F, 0x03FE, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        let trace = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let sink = trace.clone();
+        state.set_trace_hook(Box::new(move |pc, instruction| {
+            sink.borrow_mut().push((pc, *instruction));
+        }));
+        run(&mut state, &[]).unwrap();
+
+        let trace = trace.borrow();
+        let addresses: Vec<u16> = trace.iter().map(|(pc, _)| *pc).collect();
+        // Two loop iterations: the BR is taken once, then falls through.
+        assert_eq!(
+            addresses,
+            [0x3000, 0x3001, 0x3002, 0x3003, 0x3002, 0x3003, 0x3004]
+        );
+        assert_eq!(trace.last().unwrap().1, Instruction::Trap { trapvect8: 0x25 });
+    }
+
+    #[test]
+    fn test_installed_trap_handler_runs() {
+        let mut state = VmState::new();
+        // Main program: TRAP x40 / TRAP x25
+        load_words(0x3000, &[0xF040, 0xF025], &mut state);
+        // Handler: ADD R5, R5, #1 / JMP R7
+        load_words(0x3200, &[0x1B61, 0xC1C0], &mut state);
+        state.install_trap(0x40, 0x3200);
+        state[Registers::PC] = 0x3000;
+        run(&mut state, &[]).unwrap();
+        assert_eq!(state[Registers::R5], 1);
+        assert!(!state.running());
+    }
+}
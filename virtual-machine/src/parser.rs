@@ -0,0 +1,7 @@
+//! Decoding of raw memory words into [`Instruction`] values.
+//!
+//! The instruction table itself lives in the shared `lc3-isa` crate, so the
+//! assembler's encoder and this decoder cannot diverge; this module
+//! re-exports it under the VM's historical paths.
+
+pub use lc3_isa::instruction::{disassemble, BitTools, Instruction, Opcode};
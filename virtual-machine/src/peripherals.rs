@@ -0,0 +1,119 @@
+//! Memory-mapped peripherals: polled once per tick via [`Peripheral::run`],
+//! or notified the moment the program touches a register via the
+//! [`Peripheral::on_read`]/[`Peripheral::on_write`] hooks (see
+//! [`VmMemory::register_hook`]).
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::state::{VmMemory, VmState};
+
+pub const KBSR: u16 = 0xFE00;
+pub const KBDR: u16 = 0xFE02;
+pub const DSR: u16 = 0xFE04;
+pub const DDR: u16 = 0xFE06;
+
+pub trait Peripheral {
+    /// Polled once per tick, after the instruction has executed. Fine for
+    /// devices that only need tick granularity; the default does nothing.
+    fn run(&self, _state: &mut VmState) {}
+
+    /// Called the moment the program reads `address`, before the value is
+    /// fetched, so a device can refresh or clear its registers at the
+    /// instant of access (KBDR's clear-on-read, status bits computed on
+    /// demand). Only fires for registered hooks, and only on the
+    /// executor's data paths — instruction fetches and debugger views
+    /// stay side-effect free.
+    fn on_read(&self, _address: u16, _memory: &mut VmMemory) {}
+
+    /// Called the moment the program writes `address`, after the value
+    /// has landed, so a device sees the new contents immediately instead
+    /// of a tick later. Same scope as [`Peripheral::on_read`].
+    fn on_write(&self, _address: u16, _memory: &mut VmMemory) {}
+}
+
+/// Prints characters written to the display data register to stdout.
+pub struct TerminalDisplay;
+
+impl Peripheral for TerminalDisplay {
+    fn run(&self, state: &mut VmState) {
+        let character = state.memory()[DDR];
+        if character != 0 {
+            print!("{}", (character as u8) as char);
+            io::stdout().flush().ok();
+            state.memory_mut()[DDR] = 0;
+        }
+    }
+}
+
+/// Collects display output into a buffer instead of writing to stdout, for
+/// the TUI and for tests.
+#[derive(Default)]
+pub struct BufferedDisplay {
+    buffer: RefCell<String>,
+}
+
+impl BufferedDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns and clears everything the program has printed so far.
+    pub fn take(&self) -> String {
+        self.buffer.borrow_mut().split_off(0)
+    }
+}
+
+impl Peripheral for BufferedDisplay {
+    fn run(&self, state: &mut VmState) {
+        let character = state.memory()[DDR];
+        if character != 0 {
+            self.buffer.borrow_mut().push((character as u8) as char);
+            state.memory_mut()[DDR] = 0;
+        }
+    }
+}
+
+/// Feeds a fixed input string to the keyboard device.
+///
+/// Real hardware clears KBSR when KBDR is read; since peripherals only see
+/// the state between ticks, this is approximated with a tick counter: once a
+/// character has been made available it is assumed consumed after a fixed
+/// number of ticks, and the next one is queued.
+pub struct AutomatedKeyboard {
+    pending: RefCell<VecDeque<u16>>,
+    cooldown: Cell<u32>,
+}
+
+const KEYBOARD_COOLDOWN_TICKS: u32 = 50;
+
+impl AutomatedKeyboard {
+    pub fn new(input: &str) -> Self {
+        Self {
+            pending: RefCell::new(input.chars().map(|c| c as u16).collect()),
+            cooldown: Cell::new(0),
+        }
+    }
+}
+
+impl Peripheral for AutomatedKeyboard {
+    fn run(&self, state: &mut VmState) {
+        if state.memory()[KBSR] >> 15 == 1 {
+            // A character is pending; assume the program consumes it within
+            // the cooldown window.
+            let remaining = self.cooldown.get();
+            if remaining > 0 {
+                self.cooldown.set(remaining - 1);
+            } else {
+                state.memory_mut()[KBSR] = 0;
+            }
+            return;
+        }
+        if let Some(character) = self.pending.borrow_mut().pop_front() {
+            state.memory_mut()[KBDR] = character;
+            state.memory_mut()[KBSR] = 0x8000;
+            self.cooldown.set(KEYBOARD_COOLDOWN_TICKS);
+        }
+    }
+}
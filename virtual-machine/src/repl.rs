@@ -0,0 +1,696 @@
+//! Command parsing and pure formatting helpers for the interactive
+//! debugger. Keeping these free of terminal concerns makes them testable.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use tui::style::Style;
+
+use crate::peripherals::Peripheral;
+use crate::state::{Registers, VmState};
+use crate::tick;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemDisplayMode {
+    /// One 4-digit hex word per column, ASCII sidebar from the low bytes.
+    Words,
+    /// Low and high byte separately, ASCII sidebar showing both bytes,
+    /// which makes PUTSP-packed strings readable.
+    Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cmd {
+    Load(PathBuf),
+    LoadSymbols(PathBuf),
+    ViewMem(MemDisplayMode),
+    Cursor(u16),
+    UntilCursor,
+    Run,
+    Step(u64),
+    Break(String),
+    Watch(String),
+    TrapInstall { vector: u8, address: u16 },
+    InfoTraps,
+    InfoBreaks,
+    InfoWatches,
+    InfoBranches,
+    Help,
+    Quit,
+}
+
+/// Parses a number in any of the spellings the REPL accepts: `x3000`,
+/// `0x3000`, `#18` or plain decimal.
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    let parsed = if let Some(hex) = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('x'))
+        .or_else(|| text.strip_prefix('X'))
+    {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.strip_prefix('#').unwrap_or(text).parse::<u16>().ok()
+    };
+    parsed.ok_or_else(|| format!("Invalid address '{}'", text))
+}
+
+pub fn parse_command(input: &str) -> Result<Cmd, String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    match words.as_slice() {
+        [] => Err("Empty command".to_string()),
+        ["load"] => Err("'load' requires a file path".to_string()),
+        ["load", "symbols"] => Err("'load symbols' requires a file path".to_string()),
+        ["load", "symbols", ..] => {
+            let path = input
+                .trim_start()
+                .strip_prefix("load")
+                .unwrap()
+                .trim_start()
+                .strip_prefix("symbols")
+                .unwrap()
+                .trim();
+            Ok(Cmd::LoadSymbols(expand_tilde(path)))
+        }
+        // The path is everything after the command word, so paths containing
+        // spaces work without quoting.
+        ["load", ..] => {
+            let path = input.trim_start().strip_prefix("load").unwrap().trim();
+            Ok(Cmd::Load(expand_tilde(path)))
+        }
+        ["run"] | ["continue"] | ["c"] => Ok(Cmd::Run),
+        ["step"] | ["s"] => Ok(Cmd::Step(1)),
+        ["step", count] | ["s", count] => count
+            .parse::<u64>()
+            .map(Cmd::Step)
+            .map_err(|_| format!("Invalid step count '{}'", count)),
+        ["break", expr] | ["b", expr] => Ok(Cmd::Break(expr.to_string())),
+        ["watch", expr] | ["w", expr] => Ok(Cmd::Watch(expr.to_string())),
+        ["trap", "install", vector, address] => {
+            let vector = parse_address(vector)?;
+            if vector > 0xFF {
+                return Err(format!("Trap vector x{:04X} is out of range (x00-xFF)", vector));
+            }
+            Ok(Cmd::TrapInstall {
+                vector: vector as u8,
+                address: parse_address(address)?,
+            })
+        }
+        ["cursor", address] => parse_address(address).map(Cmd::Cursor),
+        ["until-cursor"] | ["uc"] => Ok(Cmd::UntilCursor),
+        ["view", "mem"] | ["view", "mem", "--words"] => Ok(Cmd::ViewMem(MemDisplayMode::Words)),
+        ["view", "mem", "--bytes"] => Ok(Cmd::ViewMem(MemDisplayMode::Bytes)),
+        ["info", "traps"] => Ok(Cmd::InfoTraps),
+        ["info", "breaks"] => Ok(Cmd::InfoBreaks),
+        ["info", "watches"] => Ok(Cmd::InfoWatches),
+        ["info", "branches"] => Ok(Cmd::InfoBranches),
+        ["help"] | ["?"] => Ok(Cmd::Help),
+        ["quit"] | ["q"] | ["exit"] => Ok(Cmd::Quit),
+        _ => Err(format!("Unknown command '{}'", input.trim())),
+    }
+}
+
+/// Expands a leading `~` to the user's home directory, leaving other paths
+/// untouched.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    expand_tilde_with(path, std::env::var_os("HOME").map(PathBuf::from))
+}
+
+fn expand_tilde_with(path: &str, home: Option<PathBuf>) -> PathBuf {
+    if let Some(home) = home {
+        if path == "~" {
+            return home;
+        }
+        if let Some(rest) = path.strip_prefix("~/") {
+            return home.join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// An address-valued debugger expression: a literal address (`x3000`), a
+/// symbol name (`COUNTER`), or a memory reference (`mem[COUNTER]`).
+///
+/// The original text is kept alongside the resolved address so the
+/// expression can be re-resolved whenever the symbol table changes; binding
+/// only the address at creation time would leave watchpoints silently
+/// pointing at stale memory after a reload moves the symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressExpr {
+    text: String,
+    address: Option<u16>,
+}
+
+impl AddressExpr {
+    /// Parses `text` and resolves it against `symbols`.
+    pub fn resolve(text: &str, symbols: &HashMap<String, u16>) -> Result<Self, String> {
+        let mut expr = Self {
+            text: text.to_string(),
+            address: None,
+        };
+        expr.reresolve(symbols)?;
+        Ok(expr)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The currently resolved address, or `None` when the last
+    /// re-resolution failed.
+    pub fn address(&self) -> Option<u16> {
+        self.address
+    }
+
+    /// Re-resolves the expression against an updated symbol table. On
+    /// failure the address is cleared, so a stale entry is disabled rather
+    /// than left watching whatever now lives at the old address.
+    pub fn reresolve(&mut self, symbols: &HashMap<String, u16>) -> Result<(), String> {
+        let name = self
+            .text
+            .strip_prefix("mem[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .unwrap_or(&self.text);
+        if let Ok(address) = parse_address(name) {
+            self.address = Some(address);
+            return Ok(());
+        }
+        match symbols.get(name) {
+            Some(address) => {
+                self.address = Some(*address);
+                Ok(())
+            }
+            None => {
+                self.address = None;
+                Err(format!("Unknown symbol '{}'", name))
+            }
+        }
+    }
+}
+
+/// The expressions one debugger feature tracks. Watchpoints, breakpoints
+/// and display expressions each keep their own set; re-resolving every set
+/// after a reload is what keeps symbolic entries current.
+#[derive(Debug, Default)]
+pub struct ExprSet {
+    entries: Vec<AddressExpr>,
+}
+
+impl ExprSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the expression, or removes it when already present (the toggle
+    /// behavior `break` has always had). Returns the resolved address on
+    /// add and `None` on removal.
+    pub fn toggle(
+        &mut self,
+        text: &str,
+        symbols: &HashMap<String, u16>,
+    ) -> Result<Option<u16>, String> {
+        if let Some(index) = self.entries.iter().position(|entry| entry.text == text) {
+            self.entries.remove(index);
+            return Ok(None);
+        }
+        let expr = AddressExpr::resolve(text, symbols)?;
+        let address = expr.address;
+        self.entries.push(expr);
+        Ok(address)
+    }
+
+    pub fn entries(&self) -> &[AddressExpr] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The currently resolved addresses, skipping disabled entries.
+    pub fn addresses(&self) -> Vec<u16> {
+        self.entries.iter().filter_map(AddressExpr::address).collect()
+    }
+
+    /// Re-resolves every entry against an updated symbol table, returning a
+    /// report line for each expression that no longer resolves.
+    pub fn reresolve(&mut self, symbols: &HashMap<String, u16>) -> Vec<String> {
+        let mut failures = Vec::new();
+        for entry in &mut self.entries {
+            if let Err(error) = entry.reresolve(symbols) {
+                failures.push(format!("'{}' no longer resolves: {}", entry.text, error));
+            }
+        }
+        failures
+    }
+}
+
+/// Labels loaded from an lc3tools `.sym` file, indexed both ways: by name
+/// for resolving debugger expressions and by address for annotating the
+/// disassembly pane.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    by_name: HashMap<String, u16>,
+    by_address: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the lc3tools `.sym` text format: a few `//`-prefixed header
+    /// lines followed by one `//\tNAME  ADDRESS` row per symbol, the
+    /// address in bare hex. Malformed rows are reported with their line
+    /// number instead of being skipped.
+    pub fn from_lc3tools_sym(text: &str) -> Result<Self> {
+        let mut table = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let content = line.strip_prefix("//").unwrap_or(line).trim();
+            if content.is_empty()
+                || content.starts_with("Symbol table")
+                || content.starts_with("Scope level")
+                || content.starts_with("Symbol Name")
+                || content.starts_with('-')
+            {
+                continue;
+            }
+            let mut parts = content.split_whitespace();
+            let (Some(name), Some(address)) = (parts.next(), parts.next()) else {
+                bail!("line {}: expected 'NAME ADDRESS', found '{}'", line_number, content);
+            };
+            if parts.next().is_some() {
+                bail!("line {}: trailing text after the address", line_number);
+            }
+            let Ok(address) = u16::from_str_radix(address, 16) else {
+                bail!("line {}: invalid hex address '{}'", line_number, address);
+            };
+            table.insert(name.to_string(), address);
+        }
+        Ok(table)
+    }
+
+    pub fn insert(&mut self, name: String, address: u16) {
+        self.by_name.insert(name.clone(), address);
+        self.by_address.insert(address, name);
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The label sitting exactly at `address`, if any.
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.by_address.get(&address).map(String::as_str)
+    }
+
+    /// The nearest label at or before `address`, for describing addresses
+    /// inside a routine.
+    pub fn nearest(&self, address: u16) -> Option<(&str, u16)> {
+        self.by_address
+            .range(..=address)
+            .next_back()
+            .map(|(address, name)| (name.as_str(), *address))
+    }
+
+    /// The name-to-address view, in the shape [`AddressExpr`] resolution
+    /// expects.
+    pub fn by_name(&self) -> &HashMap<String, u16> {
+        &self.by_name
+    }
+}
+
+/// A bounded scrollback of styled REPL messages. Oldest entries are evicted
+/// first once the capacity is reached, keeping memory use flat over long
+/// sessions.
+#[derive(Debug)]
+pub struct MessageLog {
+    entries: Vec<(Style, String)>,
+    capacity: usize,
+}
+
+impl MessageLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            capacity,
+        }
+    }
+
+    pub fn push<S: Into<String>>(&mut self, style: Style, message: S) {
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((style, message.into()));
+    }
+
+    /// The retained messages, oldest first.
+    pub fn entries(&self) -> &[(Style, String)] {
+        &self.entries
+    }
+}
+
+/// Formats the profiler's per-site branch statistics, one row per BR
+/// instruction, sorted by address.
+pub fn format_branch_table(state: &VmState) -> Vec<String> {
+    let Some(profile) = state.profile() else {
+        return vec!["Profiling is not enabled".to_string()];
+    };
+    let mut sites: Vec<_> = profile.branches().iter().collect();
+    sites.sort_by_key(|(address, _)| **address);
+    let mut rows: Vec<String> = sites
+        .into_iter()
+        .map(|(address, stats)| {
+            format!(
+                "x{:04X}  evaluated {:>8}  taken {:>8}",
+                address, stats.evaluated, stats.taken
+            )
+        })
+        .collect();
+    if rows.is_empty() {
+        rows.push("No branches were executed".to_string());
+    }
+    rows
+}
+
+/// Why a [`run_until`] call stopped ticking the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program executed a halting trap.
+    Halted,
+    /// The PC reached a breakpoint.
+    Breakpoint,
+    /// The PC reached the requested target address.
+    Target,
+    /// The safety tick cap was exhausted before anything else happened.
+    TickCap,
+}
+
+/// Ticks the machine until it halts, hits a breakpoint, reaches `target`
+/// or exhausts `cap` ticks, whichever comes first.
+pub fn run_until(
+    state: &mut VmState,
+    peripherals: &[&dyn Peripheral],
+    breakpoints: &[u16],
+    target: Option<u16>,
+    cap: u64,
+) -> Result<StopReason> {
+    for _ in 0..cap {
+        tick(state, peripherals)?;
+        if !state.running() {
+            return Ok(StopReason::Halted);
+        }
+        let pc = state[Registers::PC];
+        if target == Some(pc) {
+            return Ok(StopReason::Target);
+        }
+        if breakpoints.contains(&pc) {
+            return Ok(StopReason::Breakpoint);
+        }
+    }
+    Ok(StopReason::TickCap)
+}
+
+/// Formats one row of the memory pane like a classic hex editor: address,
+/// the words in hex, then an ASCII sidebar with a dot for anything
+/// non-printable.
+pub fn format_memory_row(address: u16, words: &[u16], mode: MemDisplayMode) -> String {
+    fn printable(byte: u16) -> char {
+        let c = (byte & 0xFF) as u8 as char;
+        if (' '..='~').contains(&c) {
+            c
+        } else {
+            '.'
+        }
+    }
+
+    let mut hex = String::new();
+    let mut ascii = String::new();
+    for word in words {
+        match mode {
+            MemDisplayMode::Words => {
+                hex.push_str(&format!("{:04X} ", word));
+                ascii.push(printable(word & 0xFF));
+            }
+            MemDisplayMode::Bytes => {
+                // Low byte first, matching the order PUTSP consumes
+                // packed characters.
+                hex.push_str(&format!("{:02X} {:02X} ", word & 0xFF, word >> 8));
+                ascii.push(printable(word & 0xFF));
+                ascii.push(printable(word >> 8));
+            }
+        }
+    }
+    format!("x{:04X}  {}|{}|", address, hex, ascii)
+}
+
+/// Lists all non-zero trap vector table entries, flagging handlers that
+/// point outside every loaded memory region.
+pub fn format_trap_table(state: &VmState) -> Vec<String> {
+    let mut rows = Vec::new();
+    for vector in 0x00..=0xFFu16 {
+        let handler = state.memory()[vector];
+        if handler == 0 {
+            continue;
+        }
+        let in_loaded_region = state
+            .loaded_regions()
+            .iter()
+            .any(|(origin, length)| handler >= *origin && (handler as u32) < *origin as u32 + *length as u32);
+        let note = if in_loaded_region {
+            ""
+        } else {
+            "  (outside any loaded region!)"
+        };
+        rows.push(format!("x{:02X} -> x{:04X}{}", vector, handler, note));
+    }
+    if rows.is_empty() {
+        rows.push("No trap vectors are installed".to_string());
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_load_keeps_spaces_in_paths() {
+        assert_eq!(
+            parse_command("load my programs/hello world.obj"),
+            Ok(Cmd::Load(PathBuf::from("my programs/hello world.obj")))
+        );
+        assert!(parse_command("load").is_err());
+    }
+
+    #[test]
+    fn test_parse_load_symbols() {
+        assert_eq!(
+            parse_command("load symbols my programs/hello world.sym"),
+            Ok(Cmd::LoadSymbols(PathBuf::from("my programs/hello world.sym")))
+        );
+        assert!(parse_command("load symbols").is_err());
+    }
+
+    #[test]
+    fn test_symbol_table_parses_the_lc3tools_sym_format() {
+        let text = "// Symbol table\n// Scope level 0:\n//\tSymbol Name       Page Address\n//\t----------------  ------------\n//\tLOOP              3002\n//\tDONE              3005\n";
+        let table = SymbolTable::from_lc3tools_sym(text).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.address_of("LOOP"), Some(0x3002));
+        assert_eq!(table.label_at(0x3005), Some("DONE"));
+        assert_eq!(table.label_at(0x3004), None);
+        // An address inside the routine resolves to the preceding label.
+        assert_eq!(table.nearest(0x3004), Some(("LOOP", 0x3002)));
+        assert_eq!(table.nearest(0x3001), None);
+    }
+
+    #[test]
+    fn test_symbol_table_reports_malformed_lines_with_their_number() {
+        let error = SymbolTable::from_lc3tools_sym("//\tLOOP              3002\n//\tBAD xNOPE\n")
+            .unwrap_err();
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        let home = Some(PathBuf::from("/home/user"));
+        assert_eq!(
+            expand_tilde_with("~/foo.obj", home.clone()),
+            PathBuf::from("/home/user/foo.obj")
+        );
+        assert_eq!(expand_tilde_with("~", home.clone()), PathBuf::from("/home/user"));
+        assert_eq!(expand_tilde_with("/abs/foo.obj", home), PathBuf::from("/abs/foo.obj"));
+        assert_eq!(expand_tilde_with("~/foo.obj", None), PathBuf::from("~/foo.obj"));
+    }
+
+    #[test]
+    fn test_parse_cursor_commands() {
+        assert_eq!(parse_command("cursor 0x3042"), Ok(Cmd::Cursor(0x3042)));
+        assert_eq!(parse_command("until-cursor"), Ok(Cmd::UntilCursor));
+        assert!(parse_command("cursor wat").is_err());
+    }
+
+    #[test]
+    fn test_watch_expressions_follow_a_symbol_across_reloads() {
+        let mut symbols = HashMap::from([("COUNTER".to_string(), 0x3010)]);
+        let mut watches = ExprSet::new();
+        watches.toggle("mem[COUNTER]", &symbols).unwrap();
+        assert_eq!(watches.addresses(), vec![0x3010]);
+
+        // A reload moved the symbol two words down; the watch follows.
+        symbols.insert("COUNTER".to_string(), 0x3012);
+        assert!(watches.reresolve(&symbols).is_empty());
+        assert_eq!(watches.addresses(), vec![0x3012]);
+    }
+
+    #[test]
+    fn test_expressions_that_fail_to_reresolve_are_reported_and_disabled() {
+        let mut symbols = HashMap::from([("COUNTER".to_string(), 0x3010)]);
+        let mut breakpoints = ExprSet::new();
+        breakpoints.toggle("COUNTER", &symbols).unwrap();
+        breakpoints.toggle("x4000", &symbols).unwrap();
+
+        symbols.clear();
+        let failures = breakpoints.reresolve(&symbols);
+        assert_eq!(
+            failures,
+            vec!["'COUNTER' no longer resolves: Unknown symbol 'COUNTER'".to_string()]
+        );
+        // The literal breakpoint is unaffected; the stale one is disabled
+        // rather than left pointing at the old address.
+        assert_eq!(breakpoints.addresses(), vec![0x4000]);
+        assert_eq!(breakpoints.entries()[0].address(), None);
+    }
+
+    #[test]
+    fn test_toggling_an_expression_twice_removes_it() {
+        let symbols = HashMap::new();
+        let mut breakpoints = ExprSet::new();
+        assert_eq!(breakpoints.toggle("x3000", &symbols), Ok(Some(0x3000)));
+        assert_eq!(breakpoints.toggle("x3000", &symbols), Ok(None));
+        assert!(breakpoints.is_empty());
+    }
+
+    #[test]
+    fn test_run_until_stops_at_the_target() {
+        let mut state = VmState::new();
+        // ADD R0, R0, #1 / ADD R0, R0, #1 / TRAP x25
+        crate::load_words(0x3000, &[0x1021, 0x1021, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        let reason = run_until(&mut state, &[], &[], Some(0x3002), 1000).unwrap();
+        assert_eq!(reason, StopReason::Target);
+        assert_eq!(state[Registers::PC], 0x3002);
+        assert_eq!(state[Registers::R0], 2);
+    }
+
+    #[test]
+    fn test_run_until_reports_a_halt_before_the_target() {
+        let mut state = VmState::new();
+        crate::load_words(0x3000, &[0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        let reason = run_until(&mut state, &[], &[], Some(0x4000), 1000).unwrap();
+        assert_eq!(reason, StopReason::Halted);
+    }
+
+    #[test]
+    fn test_run_until_honors_the_tick_cap() {
+        let mut state = VmState::new();
+        // BRnzp to itself: an infinite loop.
+        crate::load_words(0x3000, &[0x0FFF], &mut state);
+        state[Registers::PC] = 0x3000;
+        let reason = run_until(&mut state, &[], &[], Some(0x4000), 100).unwrap();
+        assert_eq!(reason, StopReason::TickCap);
+    }
+
+    #[test]
+    fn test_parse_view_mem_toggle() {
+        assert_eq!(parse_command("view mem --bytes"), Ok(Cmd::ViewMem(MemDisplayMode::Bytes)));
+        assert_eq!(parse_command("view mem --words"), Ok(Cmd::ViewMem(MemDisplayMode::Words)));
+        assert_eq!(parse_command("view mem"), Ok(Cmd::ViewMem(MemDisplayMode::Words)));
+    }
+
+    #[test]
+    fn test_format_memory_row_words() {
+        // 'H', newline (non-printable) and a packed "Hi".
+        let row = format_memory_row(0x3000, &[0x0048, 0x000A, 0x6948], MemDisplayMode::Words);
+        assert_eq!(row, "x3000  0048 000A 6948 |H.H|");
+    }
+
+    #[test]
+    fn test_format_memory_row_bytes_shows_packed_strings() {
+        let row = format_memory_row(0x3000, &[0x6948, 0x0021], MemDisplayMode::Bytes);
+        assert_eq!(row, "x3000  48 69 21 00 |Hi!.|");
+    }
+
+    #[test]
+    fn test_parse_trap_install() {
+        assert_eq!(
+            parse_command("trap install x40 0x3200"),
+            Ok(Cmd::TrapInstall {
+                vector: 0x40,
+                address: 0x3200
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_trap_install_rejects_large_vectors() {
+        assert!(parse_command("trap install x100 x3200").is_err());
+    }
+
+    #[test]
+    fn test_parse_address_spellings() {
+        assert_eq!(parse_address("x3000"), Ok(0x3000));
+        assert_eq!(parse_address("0x3000"), Ok(0x3000));
+        assert_eq!(parse_address("#18"), Ok(18));
+        assert_eq!(parse_address("18"), Ok(18));
+        assert!(parse_address("wat").is_err());
+    }
+
+    #[test]
+    fn test_message_log_evicts_oldest_entries_first() {
+        let mut log = MessageLog::new(3);
+        for message in ["one", "two", "three", "four", "five"] {
+            log.push(Style::default(), message);
+        }
+        let texts: Vec<&str> = log.entries().iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(texts, ["three", "four", "five"]);
+    }
+
+    #[test]
+    fn test_format_branch_table_lists_sites_in_address_order() {
+        let mut state = VmState::new();
+        // AND R0, R0, #0 / ADD R0, R0, #5 / ADD R0, R0, #-1 / BRp -2 / TRAP x25
+        crate::load_words(0x3000, &[0x5020, 0x1025, 0x103F, 0x03FE, 0xF025], &mut state);
+        state[Registers::PC] = 0x3000;
+        state.enable_profiling();
+        crate::run(&mut state, &[]).unwrap();
+        let rows = format_branch_table(&state);
+        assert_eq!(rows, vec!["x3003  evaluated        5  taken        4"]);
+
+        assert_eq!(
+            format_branch_table(&VmState::new()),
+            vec!["Profiling is not enabled"]
+        );
+    }
+
+    #[test]
+    fn test_format_trap_table_flags_unloaded_handlers() {
+        let mut state = VmState::new();
+        state.install_trap(0x40, 0x3200);
+        let rows = format_trap_table(&state);
+        assert_eq!(rows, vec!["x40 -> x3200  (outside any loaded region!)"]);
+    }
+}
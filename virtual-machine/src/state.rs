@@ -0,0 +1,541 @@
+//! The machine state: memory, registers and the bits of bookkeeping the
+//! debugger needs.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::rc::Rc;
+
+use lc3_isa::instruction::{ConditionFlags, Instruction};
+
+use crate::peripherals::Peripheral;
+
+/// Number of memory cells: the full 16-bit address space, so xFFFF (the
+/// topmost MMR) is indexable.
+pub const MEM_SIZE: usize = 0x10000;
+
+// The register file indices are defined alongside the instruction table;
+// re-exported here so the VM's historical `state::Registers` path keeps
+// working.
+pub use lc3_isa::instruction::Registers;
+
+/// The outcome of one `.ASSERT` checkpoint hit during execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssertionRecord {
+    /// Address of the checkpoint's TRAP word.
+    pub address: u16,
+    pub expected: u16,
+    pub actual: u16,
+}
+
+impl AssertionRecord {
+    pub fn passed(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+/// Taken/not-taken counts for a single BR site.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchStats {
+    /// How often the branch instruction was executed.
+    pub evaluated: u64,
+    /// How often the condition matched and the branch was taken.
+    pub taken: u64,
+}
+
+/// Execution statistics collected while the profiler is enabled.
+#[derive(Debug, Default)]
+pub struct Profile {
+    branches: HashMap<u16, BranchStats>,
+}
+
+impl Profile {
+    /// Per-site branch statistics, keyed by the BR instruction's address.
+    pub fn branches(&self) -> &HashMap<u16, BranchStats> {
+        &self.branches
+    }
+
+    pub(crate) fn record_branch(&mut self, address: u16, taken: bool) {
+        let stats = self.branches.entry(address).or_default();
+        stats.evaluated += 1;
+        if taken {
+            stats.taken += 1;
+        }
+    }
+}
+
+/// Addressable memory, indexed directly by `u16` addresses.
+pub struct VmMemory {
+    cells: Vec<u16>,
+    /// Bumped whenever the backing storage could have moved (a reset that
+    /// reallocates, or a future switch to sparse storage). Zero-copy
+    /// consumers — notably wasm embedders holding a raw pointer from
+    /// [`as_ptr`] — must re-check this before every use of a saved pointer.
+    ///
+    /// [`as_ptr`]: VmMemory::as_ptr
+    generation: u64,
+    /// Devices notified the moment the program reads or writes memory;
+    /// see [`VmMemory::register_hook`].
+    hooks: Vec<Rc<dyn Peripheral>>,
+}
+
+impl fmt::Debug for VmMemory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VmMemory")
+            .field("generation", &self.generation)
+            .field("hooks", &self.hooks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl VmMemory {
+    fn new() -> Self {
+        Self {
+            cells: vec![0; MEM_SIZE],
+            generation: 0,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Registers a device to be notified the instant the program reads or
+    /// writes memory, via [`Peripheral::on_read`]/[`Peripheral::on_write`].
+    /// Hooks fire only on the executor's [`read`]/[`write`] paths; plain
+    /// indexing stays side-effect free so the debugger can inspect device
+    /// registers without tripping clear-on-read semantics.
+    ///
+    /// [`read`]: VmMemory::read
+    /// [`write`]: VmMemory::write
+    pub fn register_hook(&mut self, hook: Rc<dyn Peripheral>) {
+        self.hooks.push(hook);
+    }
+
+    /// Reads `address` the way the program does: registered hooks fire
+    /// first, then the value is fetched.
+    pub fn read(&mut self, address: u16) -> u16 {
+        if !self.hooks.is_empty() {
+            // Cloned so a hook can mutate memory while we iterate.
+            let hooks = self.hooks.clone();
+            for hook in &hooks {
+                hook.on_read(address, self);
+            }
+        }
+        self.cells[address as usize]
+    }
+
+    /// Writes `address` the way the program does: the value lands first,
+    /// then registered hooks fire.
+    pub fn write(&mut self, address: u16, value: u16) {
+        self.cells[address as usize] = value;
+        if !self.hooks.is_empty() {
+            let hooks = self.hooks.clone();
+            for hook in &hooks {
+                hook.on_write(address, self);
+            }
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.cells
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u16] {
+        &mut self.cells
+    }
+
+    /// A raw pointer to the backing storage, for zero-copy embedders. The
+    /// pointer is only valid while [`generation`] returns the same value it
+    /// did when the pointer was taken; callers that cannot check (or do not
+    /// want to) should use [`copy_into`] instead.
+    ///
+    /// [`generation`]: VmMemory::generation
+    /// [`copy_into`]: VmMemory::copy_into
+    pub fn as_ptr(&self) -> *const u16 {
+        self.cells.as_ptr()
+    }
+
+    /// The current storage generation; see [`as_ptr`] for the contract.
+    ///
+    /// [`as_ptr`]: VmMemory::as_ptr
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Copies words beginning at `start` into `dst`, truncating at the end
+    /// of memory. Always safe, at the cost of a copy; the pointer-free
+    /// counterpart to [`as_ptr`].
+    ///
+    /// [`as_ptr`]: VmMemory::as_ptr
+    pub fn copy_into(&self, dst: &mut [u16], start: u16) {
+        let start = start as usize;
+        let length = dst.len().min(self.cells.len() - start.min(self.cells.len()));
+        dst[..length].copy_from_slice(&self.cells[start..start + length]);
+    }
+
+}
+
+impl Index<u16> for VmMemory {
+    type Output = u16;
+
+    fn index(&self, address: u16) -> &u16 {
+        &self.cells[address as usize]
+    }
+}
+
+impl IndexMut<u16> for VmMemory {
+    fn index_mut(&mut self, address: u16) -> &mut u16 {
+        &mut self.cells[address as usize]
+    }
+}
+
+#[derive(Debug)]
+pub struct VmState {
+    memory: VmMemory,
+    registers: [u16; 10],
+    /// Cached copy of the PSR condition bits (N/Z/P), so branch-heavy code
+    /// does not have to pull them out of the PSR on every BR.
+    condition: u16,
+    running: bool,
+    loaded_regions: Vec<(u16, u16)>,
+    /// `Some` while profiling is enabled; `None` keeps the hot path free of
+    /// bookkeeping.
+    profile: Option<Profile>,
+    assertions: Vec<AssertionRecord>,
+    strict_decode: bool,
+    /// Instructions executed since power-on (or [`VmState::reset_ticks`]),
+    /// bumped by `tick`; the single source of truth for rate displays.
+    ticks: u64,
+    /// Interrupts raised but not yet accepted; serviced between
+    /// instructions once their priority exceeds the running priority.
+    pending_interrupts: Vec<PendingInterrupt>,
+    /// Whether TRAP x20..x25 are serviced by the VM itself instead of the
+    /// vector table; see [`VmState::use_builtin_traps`].
+    builtin_traps: bool,
+    /// How far a multi-step built-in trap (PUTS/PUTSP/IN) has progressed,
+    /// carried across the ticks the routine spans.
+    trap_progress: u16,
+    /// `Some` while a trace callback is installed; see
+    /// [`VmState::set_trace_hook`].
+    trace_hook: Option<TraceHook>,
+}
+
+/// The callback type [`VmState::set_trace_hook`] accepts: the PC of the
+/// executing instruction and its decoded form.
+pub type TraceFn = Box<dyn FnMut(u16, &Instruction)>;
+
+/// See [`VmState::set_trace_hook`]; wrapped so `VmState` keeps deriving
+/// `Debug`.
+struct TraceHook(TraceFn);
+
+impl fmt::Debug for TraceHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TraceHook")
+    }
+}
+
+/// An interrupt waiting to be serviced between instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PendingInterrupt {
+    vector: u16,
+    priority: u8,
+}
+
+impl VmState {
+    pub fn new() -> Self {
+        let mut state = Self {
+            memory: VmMemory::new(),
+            registers: [0; 10],
+            condition: 0b010,
+            running: true,
+            loaded_regions: Vec::new(),
+            profile: None,
+            assertions: Vec::new(),
+            strict_decode: false,
+            ticks: 0,
+            pending_interrupts: Vec::new(),
+            builtin_traps: false,
+            trap_progress: 0,
+            trace_hook: None,
+        };
+        // The machine starts in user mode with the Z flag set, and the
+        // display starts out ready.
+        state.registers[Registers::PSR as usize] = 0x8002;
+        state.memory[crate::peripherals::DSR] = 0x8000;
+        state
+    }
+
+    pub fn memory(&self) -> &VmMemory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut VmMemory {
+        &mut self.memory
+    }
+
+    pub fn registers(&self) -> &[u16; 10] {
+        &self.registers
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    pub fn halt(&mut self) {
+        self.running = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+
+    /// Returns the machine to its power-on state. The memory storage is
+    /// reallocated, so previously saved memory pointers become invalid;
+    /// [`VmMemory::generation`] records this for zero-copy embedders.
+    pub fn reset(&mut self) {
+        let generation = self.memory.generation + 1;
+        *self = Self::new();
+        self.memory.generation = generation;
+    }
+
+    /// Sets the N/Z/P bits in the PSR according to `value`.
+    pub fn update_condition_codes(&mut self, value: u16) {
+        let condition = ConditionFlags::from_value(value) as u16;
+        let psr = self[Registers::PSR];
+        self[Registers::PSR] = (psr & !0b111) | condition;
+        self.condition = condition;
+    }
+
+    /// Writes the PSR and keeps the condition code cache in sync. Anything
+    /// that replaces the PSR wholesale (RTI, the debugger) must go through
+    /// here rather than assigning to `Registers::PSR` directly.
+    pub fn set_psr(&mut self, value: u16) {
+        self[Registers::PSR] = value;
+        self.condition = value & 0b111;
+    }
+
+    /// The cached N/Z/P condition bits. Debug builds assert that the cache
+    /// still agrees with the PSR, which catches writes that bypassed
+    /// [`VmState::set_psr`].
+    pub fn condition_codes(&self) -> (bool, bool, bool) {
+        debug_assert_eq!(
+            self.condition,
+            self[Registers::PSR] & 0b111,
+            "condition code cache is out of sync with the PSR"
+        );
+        (
+            self.condition & 0b100 != 0,
+            self.condition & 0b010 != 0,
+            self.condition & 0b001 != 0,
+        )
+    }
+
+    /// Starts collecting execution statistics, resetting any prior counts.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    /// The collected statistics, if profiling is enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub(crate) fn profile_mut(&mut self) -> Option<&mut Profile> {
+        self.profile.as_mut()
+    }
+
+    /// Whether execution validates the ISA's fixed instruction fields and
+    /// refuses to run malformed words. Off by default for compatibility
+    /// with sloppily generated object files.
+    pub fn strict_decode(&self) -> bool {
+        self.strict_decode
+    }
+
+    pub fn set_strict_decode(&mut self, enabled: bool) {
+        self.strict_decode = enabled;
+    }
+
+    /// Instructions executed so far; see [`VmState::reset_ticks`].
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Restarts the executed-instruction counter, e.g. at the start of a
+    /// measurement window.
+    pub fn reset_ticks(&mut self) {
+        self.ticks = 0;
+    }
+
+    pub(crate) fn record_tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    /// Enables the built-in TRAP x20..x25 service routines, which drive
+    /// the memory-mapped devices directly instead of dispatching through
+    /// the vector table, so small programs run without an OS image.
+    pub fn use_builtin_traps(&mut self, enabled: bool) {
+        self.builtin_traps = enabled;
+    }
+
+    pub fn builtin_traps(&self) -> bool {
+        self.builtin_traps
+    }
+
+    /// Installs a callback invoked for every executed instruction with
+    /// the PC (before the unconditional increment) and the decoded form —
+    /// the extension point for coverage and tracing tools, without having
+    /// to parse the debug log.
+    pub fn set_trace_hook(&mut self, hook: TraceFn) {
+        self.trace_hook = Some(TraceHook(hook));
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    pub(crate) fn trace(&mut self, pc: u16, instruction: &Instruction) {
+        if let Some(TraceHook(hook)) = self.trace_hook.as_mut() {
+            hook(pc, instruction);
+        }
+    }
+
+    pub(crate) fn trap_progress(&self) -> u16 {
+        self.trap_progress
+    }
+
+    pub(crate) fn advance_trap_progress(&mut self) {
+        self.trap_progress += 1;
+    }
+
+    pub(crate) fn reset_trap_progress(&mut self) {
+        self.trap_progress = 0;
+    }
+
+    /// Queues an interrupt to be serviced between instructions. `vector`
+    /// indexes the interrupt vector table at x0100 and `priority` is the
+    /// 3-bit level compared against PSR[10:8]; the interrupt stays pending
+    /// until it exceeds the running priority.
+    pub fn raise_interrupt(&mut self, vector: u16, priority: u8) {
+        self.pending_interrupts.push(PendingInterrupt { vector, priority });
+    }
+
+    /// The running priority level, PSR[10:8].
+    pub fn priority(&self) -> u8 {
+        ((self[Registers::PSR] >> 8) & 0b111) as u8
+    }
+
+    /// Removes and returns the highest-priority pending interrupt that
+    /// exceeds the running priority, if there is one.
+    pub(crate) fn take_pending_interrupt(&mut self) -> Option<(u16, u8)> {
+        let current = self.priority();
+        let index = self
+            .pending_interrupts
+            .iter()
+            .enumerate()
+            .filter(|(_, interrupt)| interrupt.priority > current)
+            .max_by_key(|(_, interrupt)| interrupt.priority)
+            .map(|(index, _)| index)?;
+        let interrupt = self.pending_interrupts.remove(index);
+        Some((interrupt.vector, interrupt.priority))
+    }
+
+    /// The `.ASSERT` checkpoints hit so far, in execution order.
+    pub fn assertion_records(&self) -> &[AssertionRecord] {
+        &self.assertions
+    }
+
+    pub(crate) fn record_assertion(&mut self, record: AssertionRecord) {
+        self.assertions.push(record);
+    }
+
+    /// Points the trap vector table entry for `vector` at `handler_addr`.
+    /// The table occupies x0000 through x00FF.
+    pub fn install_trap(&mut self, vector: u8, handler_addr: u16) {
+        self.memory[vector as u16] = handler_addr;
+    }
+
+    /// Memory regions that have been populated by a loader, as
+    /// `(origin, length)` pairs. Used by the debugger to flag trap vectors
+    /// pointing into uninitialized memory.
+    pub fn loaded_regions(&self) -> &[(u16, u16)] {
+        &self.loaded_regions
+    }
+
+    pub(crate) fn record_loaded_region(&mut self, origin: u16, length: u16) {
+        self.loaded_regions.push((origin, length));
+    }
+}
+
+impl Default for VmState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<Registers> for VmState {
+    type Output = u16;
+
+    fn index(&self, register: Registers) -> &u16 {
+        &self.registers[register as usize]
+    }
+}
+
+impl IndexMut<Registers> for VmState {
+    fn index_mut(&mut self, register: Registers) -> &mut u16 {
+        &mut self.registers[register as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_condition_codes_keeps_cache_in_sync() {
+        let mut state = VmState::new();
+        state.update_condition_codes(0x8000);
+        assert_eq!(state.condition_codes(), (true, false, false));
+        state.update_condition_codes(0);
+        assert_eq!(state.condition_codes(), (false, true, false));
+        state.update_condition_codes(1);
+        assert_eq!(state.condition_codes(), (false, false, true));
+    }
+
+    #[test]
+    fn test_set_psr_updates_the_cache() {
+        let mut state = VmState::new();
+        // A debugger writing the PSR wholesale must not leave the cached
+        // condition codes stale.
+        state.set_psr(0x8004);
+        assert_eq!(state.condition_codes(), (true, false, false));
+        state.set_psr(0x8001);
+        assert_eq!(state.condition_codes(), (false, false, true));
+    }
+
+    #[test]
+    fn test_memory_generation_bumps_across_a_reset() {
+        let mut state = VmState::new();
+        let generation = state.memory().generation();
+        state.reset();
+        // The backing storage was reallocated; a pointer saved before the
+        // reset must not be trusted any more.
+        assert_eq!(state.memory().generation(), generation + 1);
+    }
+
+    #[test]
+    fn test_copy_into_matches_pointer_reads() {
+        let mut state = VmState::new();
+        state.memory_mut()[0x3000] = 0xBEEF;
+        state.memory_mut()[0x3001] = 0x1234;
+
+        let mut buffer = [0u16; 2];
+        state.memory().copy_into(&mut buffer, 0x3000);
+        assert_eq!(buffer, [0xBEEF, 0x1234]);
+        assert_eq!(buffer[0], unsafe {
+            *state.memory().as_ptr().add(0x3000)
+        });
+
+        // Truncates at the end of memory instead of panicking.
+        let mut buffer = [0xFFFFu16; 4];
+        state.memory().copy_into(&mut buffer, (MEM_SIZE - 2) as u16);
+        assert_eq!(buffer[2..], [0xFFFF, 0xFFFF]);
+    }
+}
@@ -0,0 +1,8 @@
+/// Sign extension is defined in the shared ISA crate so the decoder cannot
+/// diverge from the assembler's field encoding.
+pub use lc3_isa::fields::sign_extend;
+
+/// Two's-complement addition on raw words.
+pub fn binary_add(a: u16, b: u16) -> u16 {
+    a.wrapping_add(b)
+}
@@ -0,0 +1,67 @@
+//! End-to-end tests for the `lc3run` binary: assemble-and-run in one step,
+//! checking exit codes and output streams.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Writes `source` to a uniquely named file in the temp directory and
+/// returns its path.
+fn write_source(name: &str, source: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("lc3run_test_{}.asm", name));
+    fs::write(&path, source).expect("could not write the test program");
+    path
+}
+
+fn lc3run() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_lc3run"))
+}
+
+#[test]
+fn test_a_program_that_halts_prints_its_output_and_exits_zero() {
+    // The built-in trap handlers are on by default, so PUTS works without
+    // loading an OS image first.
+    let path = write_source(
+        "hello",
+        r#"
+.ORIG x3000
+        LEA R0, MESSAGE
+        PUTS
+        HALT
+MESSAGE .STRINGZ "Hello, World!"
+.END
+"#,
+    );
+    let output = lc3run().arg(&path).output().expect("lc3run did not start");
+    assert!(output.status.success(), "stderr: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello, World!");
+}
+
+#[test]
+fn test_assembly_errors_go_to_stderr_with_a_failing_exit_code() {
+    let path = write_source(
+        "bad",
+        r#"
+.ORIG x3000
+        ADD R0, R0, R9
+        HALT
+.END
+"#,
+    );
+    let output = lc3run().arg(&path).output().expect("lc3run did not start");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("R9"), "stderr: {}", stderr);
+}
+
+#[test]
+fn test_a_missing_source_file_is_reported() {
+    let output = lc3run()
+        .arg("/nonexistent/does_not_exist.asm")
+        .output()
+        .expect("lc3run did not start");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not read"), "stderr: {}", stderr);
+}